@@ -0,0 +1,81 @@
+use gc::{GcGraphVisitor, GcObject};
+
+const TYPE_COUNT : usize = 0x80;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GcTypeCensus {
+	pub ty: u32,
+	pub objects: usize,
+	pub bytes: usize,
+	pub arrays: usize,
+	pub array_bytes: usize
+}
+
+impl GcTypeCensus {
+	fn new(ty: u32) -> GcTypeCensus {
+		GcTypeCensus {
+			ty: ty,
+			objects: 0,
+			bytes: 0,
+			arrays: 0,
+			array_bytes: 0
+		}
+	}
+	
+	pub fn non_arrays(&self) -> usize {
+		self.objects - self.arrays
+	}
+	
+	pub fn non_array_bytes(&self) -> usize {
+		self.bytes - self.array_bytes
+	}
+}
+
+/// Object counts and sizes of the live heap, grouped by type id.
+#[derive(Clone, Debug)]
+pub struct GcCensus {
+	types: Vec<GcTypeCensus>
+}
+
+impl GcCensus {
+	pub fn new() -> GcCensus {
+		GcCensus {
+			types: (0..TYPE_COUNT).map(|ty| GcTypeCensus::new(ty as u32)).collect()
+		}
+	}
+	
+	pub fn add(&mut self, ty: u32, bytes: usize, is_array: bool) {
+		let entry = &mut self.types[ty as usize];
+		
+		entry.objects += 1;
+		entry.bytes += bytes;
+		
+		if is_array {
+			entry.arrays += 1;
+			entry.array_bytes += bytes;
+		}
+	}
+	
+	pub fn get(&self, ty: u32) -> &GcTypeCensus {
+		&self.types[ty as usize]
+	}
+	
+	/// Returns the census of all types that have at least one live object.
+	pub fn types(&self) -> Vec<&GcTypeCensus> {
+		self.types.iter().filter(|entry| entry.objects > 0).collect()
+	}
+	
+	pub fn total_objects(&self) -> usize {
+		self.types.iter().fold(0, |total, entry| total + entry.objects)
+	}
+	
+	pub fn total_bytes(&self) -> usize {
+		self.types.iter().fold(0, |total, entry| total + entry.bytes)
+	}
+}
+
+impl GcGraphVisitor for GcCensus {
+	fn object(&mut self, object: &GcObject) {
+		self.add(object.ty, object.size, object.is_array);
+	}
+}
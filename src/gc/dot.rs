@@ -0,0 +1,75 @@
+// Writes the live heap as a Graphviz graph. This is meant for small heaps,
+// e.g. to visualize what a collection will keep alive.
+
+use gc::{GcHeap, GcRoot, GcObject, GcGraphVisitor, ptr_t};
+use std::io;
+use std::io::Write;
+
+struct DotWriter<'a> {
+	writer: &'a mut Write,
+	result: io::Result<()>
+}
+
+impl<'a> DotWriter<'a> {
+	fn write(&mut self, args: ::std::fmt::Arguments) {
+		if self.result.is_ok() {
+			self.result = self.writer.write_fmt(args);
+		}
+	}
+}
+
+impl<'a> GcGraphVisitor for DotWriter<'a> {
+	fn root(&mut self, root: GcRoot, target: ptr_t) {
+		match root {
+			GcRoot::Handle(index) => {
+				self.write(format_args!("\t\"h{}\" [label=\"Root {}\", shape=ellipse, style=filled, fillcolor=lightgrey];\n", index, index));
+				self.write(format_args!("\t\"h{}\" -> \"{:?}\";\n", index, target));
+			}
+			GcRoot::Local { scope, index } => {
+				self.write(format_args!("\t\"l{}_{}\" [label=\"Local {}:{}\", shape=ellipse, style=filled, fillcolor=lightblue];\n", scope, index, scope, index));
+				self.write(format_args!("\t\"l{}_{}\" -> \"{:?}\";\n", scope, index, target));
+			}
+		}
+	}
+	
+	fn object(&mut self, object: &GcObject) {
+		if object.is_array {
+			self.write(format_args!(
+				"\t\"{:?}\" [label=\"{:?}\\ntype {}\\n{} bytes\\nlen {}\"];\n",
+				object.ptr, object.ptr, object.ty, object.size, object.len
+			));
+		} else {
+			self.write(format_args!(
+				"\t\"{:?}\" [label=\"{:?}\\ntype {}\\n{} bytes\"];\n",
+				object.ptr, object.ptr, object.ty, object.size
+			));
+		}
+	}
+	
+	fn edge(&mut self, from: ptr_t, offset: usize, to: ptr_t) {
+		self.write(format_args!("\t\"{:?}\" -> \"{:?}\" [label=\"@{}\"];\n", from, to, offset));
+	}
+}
+
+impl GcHeap {
+	/// Writes all live objects and the pointers between them in the Graphviz
+	/// DOT format. Roots are written as separate, filled nodes.
+	pub fn dump_dot(&self, writer: &mut Write) -> io::Result<()> {
+		writer.write_all(b"digraph heap {\n\tnode [shape=box];\n")?;
+		
+		let result = {
+			let mut dot = DotWriter {
+				writer: writer,
+				result: Ok(())
+			};
+			
+			self.walk_graph(&mut dot);
+			
+			dot.result
+		};
+		
+		result?;
+		
+		writer.write_all(b"}\n")
+	}
+}
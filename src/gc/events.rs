@@ -0,0 +1,154 @@
+use gc::GcObject;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GcReason {
+	/// The collection was requested through `GcHeap::gc`.
+	Requested,
+	/// An allocation did not fit in the current heap.
+	AllocationFailed,
+	/// The allocation budget since the last collection was used up.
+	AllocationBudget,
+	/// The trigger set through `GcHeap::set_gc_trigger` requested a collection.
+	Trigger,
+	/// The embedder had idle time through `GcHeap::collect_if_worthwhile`.
+	Idle,
+	/// The embedder received a low memory notification.
+	LowMemory
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GcKind {
+	/// A cheap collection that may only collect recently allocated objects.
+	Minor,
+	/// A full collection.
+	Major,
+	/// A full collection that also removes fragmentation.
+	Compacting,
+	/// A full collection that returns as much memory as possible to the
+	/// operating system.
+	Shrinking
+}
+
+/// A collection requested through `GcHeap::gc_with`. Strategies perform
+/// the closest kind of collection they support.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GcRequest {
+	pub kind: GcKind,
+	pub reason: GcReason
+}
+
+impl GcRequest {
+	pub fn new(kind: GcKind, reason: GcReason) -> GcRequest {
+		GcRequest {
+			kind: kind,
+			reason: reason
+		}
+	}
+}
+
+/// The information passed to a trigger set through `GcHeap::set_gc_trigger`.
+#[derive(Copy, Clone, Debug)]
+pub struct GcTriggerInfo {
+	/// Bytes allocated since the last collection.
+	pub allocated_since_gc: usize,
+	pub mem_allocated: usize,
+	pub mem_used: usize
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GcCollectionStats {
+	pub reason: GcReason,
+	/// The kind of collection that was requested.
+	pub requested_kind: GcKind,
+	/// The kind of collection the strategy performed.
+	pub kind: GcKind,
+	pub bytes_before: usize,
+	pub bytes_after: usize,
+	pub bytes_copied: usize,
+	pub objects_copied: usize,
+	pub pause_ns: u64,
+	pub heap_size_before: usize,
+	pub heap_size_after: usize
+}
+
+impl GcCollectionStats {
+	pub fn new(request: &GcRequest) -> GcCollectionStats {
+		GcCollectionStats {
+			reason: request.reason,
+			requested_kind: request.kind,
+			kind: request.kind,
+			bytes_before: 0,
+			bytes_after: 0,
+			bytes_copied: 0,
+			objects_copied: 0,
+			pause_ns: 0,
+			heap_size_before: 0,
+			heap_size_after: 0
+		}
+	}
+}
+
+/// A root or external reference that was still alive when its heap was
+/// dropped.
+#[derive(Copy, Clone, Debug)]
+pub struct GcLeakedRoot {
+	pub handle: u32,
+	/// The object the root referenced. Its memory is released with the heap.
+	pub object: GcObject
+}
+
+pub trait GcListener {
+	fn before_gc(&self, _request: &GcRequest) {}
+	
+	fn after_gc(&self, _stats: &GcCollectionStats) {}
+	
+	/// Called when the heap is dropped with the roots that are still alive.
+	/// These roots are invalidated after this call.
+	fn heap_dropped(&self, _leaked: &[GcLeakedRoot]) {}
+}
+
+/// Snapshot of the cumulative heap statistics as returned by `GcHeap::stats`.
+#[derive(Copy, Clone, Debug)]
+pub struct GcHeapStats {
+	/// Total number of bytes allocated since the heap was created.
+	pub total_allocated: usize,
+	pub mem_allocated: usize,
+	pub mem_used: usize,
+	pub collections: usize,
+	/// Number of collections by the kind that was performed.
+	pub minor_collections: usize,
+	pub major_collections: usize,
+	pub compacting_collections: usize,
+	pub shrinking_collections: usize,
+	pub total_pause_ns: u64,
+	pub max_pause_ns: u64,
+	pub bytes_copied: usize,
+	pub root_handles: usize,
+	pub local_handles: usize,
+	/// Fill factor of the heap after the last collection.
+	pub last_used: f64,
+	/// Size of the allocation that triggered the pending collection.
+	pub last_failed: usize
+}
+
+impl GcHeapStats {
+	pub fn new() -> GcHeapStats {
+		GcHeapStats {
+			total_allocated: 0,
+			mem_allocated: 0,
+			mem_used: 0,
+			collections: 0,
+			minor_collections: 0,
+			major_collections: 0,
+			compacting_collections: 0,
+			shrinking_collections: 0,
+			total_pause_ns: 0,
+			max_pause_ns: 0,
+			bytes_copied: 0,
+			root_handles: 0,
+			local_handles: 0,
+			last_used: 0f64,
+			last_failed: 0
+		}
+	}
+}
@@ -0,0 +1,218 @@
+use gc::{GcHeap, GcMemHeader, AsPtr, walk_object, object_size, ptr_t};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem::transmute;
+
+/// Identifies where a reference from outside the heap is stored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GcRoot {
+	/// A `Root` or `ArrayRoot` with the given handle index.
+	Handle(u32),
+	/// A slot in a local scope. Scopes are numbered from the outermost one and
+	/// slots in the order they were allocated.
+	Local {
+		scope: usize,
+		index: usize
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GcObject {
+	pub ptr: ptr_t,
+	pub ty: u32,
+	/// Size of the object in bytes including its header.
+	pub size: usize,
+	pub is_array: bool,
+	/// Number of items when this is an array; zero otherwise.
+	pub len: usize
+}
+
+impl GcObject {
+	pub unsafe fn from_ptr(ptr: ptr_t) -> GcObject {
+		let gc_header = GcMemHeader::from_ptr(ptr);
+		let is_array = gc_header.is_array();
+		
+		GcObject {
+			ptr: ptr,
+			ty: gc_header.get_type_id(),
+			size: object_size(ptr),
+			is_array: is_array,
+			len: if !is_array {
+				0
+			} else if gc_header.is_vector() {
+				// The storage of a vector stores its capacity first.
+				*transmute::<_, *const usize>(ptr).offset(1)
+			} else {
+				*transmute::<_, *const usize>(ptr)
+			}
+		}
+	}
+}
+
+/// Receives the live heap graph from `GcHeap::walk_graph`. Roots are reported
+/// first, after which objects are visited in breadth first order. Every object
+/// is reported once, followed by all its outgoing edges.
+pub trait GcGraphVisitor {
+	fn root(&mut self, _root: GcRoot, _target: ptr_t) {}
+	
+	fn object(&mut self, _object: &GcObject) {}
+	
+	/// Reports a pointer stored at byte offset `offset` of `from`.
+	fn edge(&mut self, _from: ptr_t, _offset: usize, _to: ptr_t) {}
+}
+
+impl GcHeap {
+	/// Traces the live heap without moving any objects. On a shared heap the
+	/// other mutators are stopped while tracing, but only the roots of this
+	/// mutator are reported.
+	pub fn walk_graph(&self, visitor: &mut GcGraphVisitor) {
+		self.with_world_stopped(|_| self.trace_graph(visitor));
+	}
+	
+	fn trace_graph(&self, visitor: &mut GcGraphVisitor) {
+		let mut seen = HashSet::new();
+		let mut queue = VecDeque::new();
+		
+		self.each_root(&mut |root, target| {
+			visitor.root(root, target);
+			
+			if seen.insert(target) {
+				queue.push_back(target);
+			}
+		});
+		
+		while let Some(ptr) = queue.pop_front() {
+			unsafe {
+				visitor.object(&GcObject::from_ptr(ptr));
+				
+				walk_object(ptr, self.walker(), |child| {
+					let target = child.get();
+					
+					visitor.edge(ptr, child.offset_from(ptr), target);
+					
+					if seen.insert(target) {
+						queue.push_back(target);
+					}
+				});
+			}
+		}
+	}
+	
+	fn each_root(&self, f: &mut FnMut(GcRoot, ptr_t)) {
+		{
+			let handles = self.handles.data.borrow();
+			
+			for (index, &ptr) in handles.ptrs.iter().enumerate() {
+				if !ptr.is_null() {
+					f(GcRoot::Handle(index as u32), ptr);
+				}
+			}
+		}
+		
+		let scopes = self.scopes.borrow();
+		
+		for (scope, data) in scopes.iter().enumerate() {
+			let ptrs = data.handles.iter().flat_map(|handles| handles.iter()).chain(data.current.iter());
+			
+			for (index, &ptr) in ptrs.enumerate() {
+				if !ptr.is_null() {
+					f(GcRoot::Local { scope: scope, index: index }, ptr);
+				}
+			}
+		}
+	}
+}
+
+/// A pointer stored at byte offset `offset` of `from`, referencing `to`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GcEdge {
+	pub from: ptr_t,
+	pub offset: usize,
+	pub to: ptr_t
+}
+
+/// The chain of references through which a root keeps an object alive.
+#[derive(Clone, Debug)]
+pub struct GcRetainingPath {
+	pub root: GcRoot,
+	/// The edges from the object referenced by the root up to the target. This
+	/// is empty when the root references the target directly.
+	pub edges: Vec<GcEdge>
+}
+
+enum Parent {
+	Root(GcRoot),
+	Edge(GcEdge)
+}
+
+impl GcHeap {
+	/// Returns the shortest path from a root to the object, or `None` when the
+	/// object is not reachable. When `obj` is a `Root`, its own handle will be
+	/// the result; use `Root::retaining_path` to look past it.
+	pub fn retaining_path<T, U: AsPtr<T>>(&self, obj: &U) -> Option<GcRetainingPath> {
+		self.find_retaining_path(obj.as_ptr().ptr(), None)
+	}
+	
+	pub fn find_retaining_path(&self, target: ptr_t, ignore: Option<GcRoot>) -> Option<GcRetainingPath> {
+		self.with_world_stopped(|_| self.search_retaining_path(target, ignore))
+	}
+	
+	fn search_retaining_path(&self, target: ptr_t, ignore: Option<GcRoot>) -> Option<GcRetainingPath> {
+		if target.is_null() {
+			return None;
+		}
+		
+		let mut parents = HashMap::new();
+		let mut queue = VecDeque::new();
+		
+		self.each_root(&mut |root, ptr| {
+			if Some(root) != ignore && !parents.contains_key(&ptr) {
+				parents.insert(ptr, Parent::Root(root));
+				queue.push_back(ptr);
+			}
+		});
+		
+		while !parents.contains_key(&target) {
+			let ptr = match queue.pop_front() {
+				Some(ptr) => ptr,
+				None => return None
+			};
+			
+			unsafe {
+				walk_object(ptr, self.walker(), |child| {
+					let to = child.get();
+					
+					if !parents.contains_key(&to) {
+						parents.insert(to, Parent::Edge(GcEdge {
+							from: ptr,
+							offset: child.offset_from(ptr),
+							to: to
+						}));
+						queue.push_back(to);
+					}
+				});
+			}
+		}
+		
+		// Walk back from the target to the root.
+		
+		let mut edges = Vec::new();
+		let mut ptr = target;
+		
+		loop {
+			match parents[&ptr] {
+				Parent::Root(root) => {
+					edges.reverse();
+					
+					return Some(GcRetainingPath {
+						root: root,
+						edges: edges
+					});
+				}
+				Parent::Edge(edge) => {
+					edges.push(edge);
+					ptr = edge.from;
+				}
+			}
+		}
+	}
+}
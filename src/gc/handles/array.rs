@@ -1,4 +1,4 @@
-use gc::{GcHeap, ArrayLocal, ptr_t};
+use gc::{GcHeap, ArrayLocal, FieldPtr, ptr_t};
 use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::marker::PhantomData;
 use std::ptr;
@@ -38,6 +38,13 @@ impl<T> Array<T> {
 	pub fn as_local(&self, heap: &GcHeap) -> ArrayLocal<T> {
 		heap.alloc_array_local_from_ptr(*self)
 	}
+	
+	/// Returns a reference to the item at `index`.
+	pub fn item_ptr(&self, index: usize) -> FieldPtr<T> {
+		assert!(index < self.len());
+		
+		unsafe { FieldPtr::from_base(self.ptr, size_of::<usize>() + index * size_of::<T>()) }
+	}
 }
 
 impl<T> Copy for Array<T> {}
@@ -1,4 +1,4 @@
-use gc::{Array, ArrayLocal, RootHandles, GcHeap, AsArray, AsPtr};
+use gc::{Array, ArrayLocal, RootHandles, GcHeap, GcRoot, GcRetainingPath, GcHeapId, AsArray, AsPtr};
 use std::ops::{Deref, DerefMut};
 use std::marker::PhantomData;
 use std::mem::{size_of, transmute};
@@ -13,6 +13,8 @@ pub struct ArrayRoot<T> {
 
 impl<'a, T> ArrayRoot<T> {
 	pub unsafe fn new<U: AsArray<T>>(heap: &'a GcHeap, ptr: U) -> ArrayRoot<T> {
+		heap.check_owns(ptr.as_ptr().ptr());
+		
 		ArrayRoot {
 			handles: heap.handles.clone(),
 			handle: heap.handles.add(ptr.as_ptr().ptr()),
@@ -21,8 +23,44 @@ impl<'a, T> ArrayRoot<T> {
 	}
 	
 	pub fn as_local(&self, heap: &GcHeap) -> ArrayLocal<T> {
+		debug_assert!(self.handles.heap == heap.id(), "ArrayRoot belongs to heap {:?} instead of {:?}", self.handles.heap, heap.id());
+		
 		heap.alloc_array_local_from_ptr(self.as_ptr())
 	}
+	
+	/// Returns the items, or `None` when the heap of the root was dropped.
+	pub fn try_get(&self) -> Option<&[T]> {
+		unsafe {
+			self.handles.try_get_target(self.handle).map(|ptr| {
+				let size = *transmute::<_, *const usize>(ptr);
+				let ptr = ptr.offset(size_of::<usize>() as isize);
+				
+				slice::from_raw_parts(transmute(ptr), size)
+			})
+		}
+	}
+	
+	pub fn try_get_mut(&mut self) -> Option<&mut [T]> {
+		unsafe {
+			self.handles.try_get_target(self.handle).map(|ptr| {
+				let size = *transmute::<_, *const usize>(ptr);
+				let ptr = ptr.offset(size_of::<usize>() as isize);
+				
+				slice::from_raw_parts_mut(transmute(ptr), size)
+			})
+		}
+	}
+	
+	/// Returns the heap this root was created from.
+	pub fn heap_id(&self) -> GcHeapId {
+		self.handles.heap
+	}
+	
+	/// Returns the shortest path through which something other than this
+	/// root keeps the array alive.
+	pub fn retaining_path(&self, heap: &GcHeap) -> Option<GcRetainingPath> {
+		heap.find_retaining_path(self.as_ptr().ptr(), Some(GcRoot::Handle(self.handle)))
+	}
 }
 
 impl<T> Deref for ArrayRoot<T> {
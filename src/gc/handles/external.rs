@@ -0,0 +1,100 @@
+use gc::{Ptr, Local, Root, GcHeap, GcHeapId, AsPtr};
+use std::marker::PhantomData;
+
+/// A reference to an object of a specific heap that may be stored anywhere,
+/// including in objects of other heaps. The object is kept alive until the
+/// reference is released through `GcHeap::release_external`.
+///
+/// An external reference is plain data: walkers must report it as
+/// `GcWalk::Skip` when it is stored in an object. Using a copy of a released
+/// reference panics, even when its handle was reused since.
+pub struct External<T> {
+	heap: GcHeapId,
+	handle: u32,
+	generation: u32,
+	_type: PhantomData<T>
+}
+
+impl<T> External<T> {
+	/// Returns the heap the object belongs to.
+	pub fn heap_id(&self) -> GcHeapId {
+		self.heap
+	}
+	
+	/// Returns the current location of the object. Panics when `heap` is not
+	/// the heap the reference was created from.
+	pub fn get(&self, heap: &GcHeap) -> Ptr<T> {
+		self.check_heap(heap);
+		
+		let ptr = unsafe { heap.handles.get_target(self.handle) };
+		self.check_released(heap);
+		
+		Ptr::from_ptr(ptr)
+	}
+	
+	pub fn as_local(&self, heap: &GcHeap) -> Local<T> {
+		self.get(heap).as_local(heap)
+	}
+	
+	pub fn as_root(&self, heap: &GcHeap) -> Root<T> {
+		unsafe { Root::new(heap, self.get(heap)) }
+	}
+	
+	fn check_heap(&self, heap: &GcHeap) {
+		if self.heap != heap.id() {
+			panic!("External belongs to heap {:?} instead of {:?}", self.heap, heap.id());
+		}
+	}
+	
+	fn check_released(&self, heap: &GcHeap) {
+		if heap.handles.generation(self.handle) != self.generation {
+			panic!("External was already released");
+		}
+	}
+}
+
+impl<T> Copy for External<T> {}
+
+impl<T> Clone for External<T> {
+	fn clone(&self) -> External<T> {
+		External {
+			heap: self.heap,
+			handle: self.handle,
+			generation: self.generation,
+			_type: PhantomData
+		}
+	}
+}
+
+impl GcHeap {
+	/// Creates a reference to an object of this heap that can be stored in
+	/// other heaps.
+	pub fn new_external<T, U: AsPtr<T>>(&self, ptr: &U) -> External<T> {
+		let ptr = ptr.as_ptr().ptr();
+		if ptr.is_null() {
+			panic!("Cannot create an External for a null pointer");
+		}
+		
+		self.check_owns(ptr);
+		
+		let handle = self.handles.add(ptr);
+		
+		External {
+			heap: self.id(),
+			handle: handle,
+			generation: self.handles.generation(handle),
+			_type: PhantomData
+		}
+	}
+	
+	/// Releases the object referenced by the external reference. All copies of
+	/// the reference become invalid.
+	pub fn release_external<T>(&self, external: External<T>) {
+		external.check_heap(self);
+		
+		unsafe { self.handles.get_target(external.handle) };
+		external.check_released(self);
+		
+		self.handles.remove(external.handle);
+	}
+}
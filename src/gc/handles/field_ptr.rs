@@ -0,0 +1,92 @@
+use gc::{GcHeap, FieldRoot, ptr_t};
+use std::ops::{Deref, DerefMut};
+use std::marker::PhantomData;
+use std::ptr;
+use std::mem::transmute;
+use std::fmt;
+
+/// A reference to a field of an object or an item of an array. The collector
+/// can only update references to the start of an object, so this stores the
+/// start of the object together with the offset of the field.
+///
+/// When a field pointer is stored in an object, walkers must report its first
+/// word, the object, as `GcWalk::Pointer` and its second word, the offset, as
+/// `GcWalk::Skip`.
+#[repr(C)]
+pub struct FieldPtr<T> {
+	base: ptr_t,
+	offset: usize,
+	_type: PhantomData<T>
+}
+
+impl<T> FieldPtr<T> {
+	/// Creates a reference to the `T` at byte offset `offset` of the object
+	/// that starts at `base`.
+	pub unsafe fn from_base(base: ptr_t, offset: usize) -> FieldPtr<T> {
+		FieldPtr {
+			base: base,
+			offset: offset,
+			_type: PhantomData
+		}
+	}
+	
+	pub fn null() -> FieldPtr<T> {
+		unsafe { Self::from_base(ptr::null(), 0) }
+	}
+	
+	pub fn is_null(&self) -> bool {
+		self.base.is_null()
+	}
+	
+	/// Returns the start of the object the field belongs to.
+	pub fn base(&self) -> ptr_t {
+		self.base
+	}
+	
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+	
+	/// Returns the current location of the field.
+	pub fn ptr(&self) -> ptr_t {
+		unsafe { self.base.offset(self.offset as isize) }
+	}
+	
+	pub fn as_root(&self, heap: &GcHeap) -> FieldRoot<T> {
+		unsafe { FieldRoot::new(heap, *self) }
+	}
+}
+
+impl<T> PartialEq for FieldPtr<T> {
+	fn eq(&self, other: &FieldPtr<T>) -> bool {
+		self.base == other.base && self.offset == other.offset
+	}
+}
+
+impl<T> Copy for FieldPtr<T> { }
+
+impl<T> Clone for FieldPtr<T> {
+	fn clone(&self) -> FieldPtr<T> {
+		*self
+	}
+}
+
+impl<T> fmt::Debug for FieldPtr<T> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmt, "FieldPtr {{ base: {:?}, offset: {} }}", self.base, self.offset)
+	}
+}
+
+impl<T> Deref for FieldPtr<T> {
+	type Target = T;
+	
+	fn deref(&self) -> &T {
+		unsafe { transmute(self.ptr()) }
+	}
+}
+
+impl<T> DerefMut for FieldPtr<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { transmute(self.ptr()) }
+	}
+}
@@ -0,0 +1,83 @@
+use gc::{FieldPtr, RootHandles, GcHeap, GcHeapId};
+use std::ops::{Deref, DerefMut};
+use std::marker::PhantomData;
+use std::mem::transmute;
+use std::rc::Rc;
+
+/// A root for a field of an object or an item of an array. This keeps the
+/// whole object alive.
+pub struct FieldRoot<T> {
+	handles: Rc<RootHandles>,
+	handle: u32,
+	offset: usize,
+	_type: PhantomData<T>
+}
+
+impl<T> FieldRoot<T> {
+	pub unsafe fn new(heap: &GcHeap, ptr: FieldPtr<T>) -> FieldRoot<T> {
+		heap.check_owns(ptr.base());
+		
+		FieldRoot {
+			handles: heap.handles.clone(),
+			handle: heap.handles.add(ptr.base()),
+			offset: ptr.offset(),
+			_type: PhantomData
+		}
+	}
+	
+	/// Returns the current location of the field.
+	pub fn as_field_ptr(&self) -> FieldPtr<T> {
+		unsafe { FieldPtr::from_base(self.handles.get_target(self.handle), self.offset) }
+	}
+	
+	/// Returns the field, or `None` when the heap of the root was dropped.
+	pub fn try_get(&self) -> Option<&T> {
+		unsafe { self.handles.try_get_target(self.handle).map(|ptr| transmute(ptr.offset(self.offset as isize))) }
+	}
+	
+	pub fn try_get_mut(&mut self) -> Option<&mut T> {
+		unsafe { self.handles.try_get_target(self.handle).map(|ptr| transmute(ptr.offset(self.offset as isize))) }
+	}
+	
+	/// Returns the heap this root was created from.
+	pub fn heap_id(&self) -> GcHeapId {
+		self.handles.heap
+	}
+}
+
+impl<T> Deref for FieldRoot<T> {
+	type Target = T;
+	
+	fn deref(&self) -> &T {
+		unsafe {
+			let ptr = self.handles.get_target(self.handle);
+			transmute(ptr.offset(self.offset as isize))
+		}
+	}
+}
+
+impl<T> DerefMut for FieldRoot<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe {
+			let ptr = self.handles.get_target(self.handle);
+			transmute(ptr.offset(self.offset as isize))
+		}
+	}
+}
+
+impl<T> Clone for FieldRoot<T> {
+	fn clone(&self) -> FieldRoot<T> {
+		FieldRoot {
+			handles: self.handles.clone(),
+			handle: self.handles.clone_root(self.handle),
+			offset: self.offset,
+			_type: PhantomData
+		}
+	}
+}
+
+impl<T> Drop for FieldRoot<T> {
+	fn drop(&mut self) {
+		self.handles.remove(self.handle);
+	}
+}
@@ -1,6 +1,9 @@
 pub mod array_local;
 pub mod array_root;
 pub mod array;
+pub mod external;
+pub mod field_ptr;
+pub mod field_root;
 pub mod local;
 pub mod ptr;
 pub mod root;
@@ -8,6 +11,9 @@ pub mod root;
 pub use self::array_local::ArrayLocal;
 pub use self::array_root::ArrayRoot;
 pub use self::array::{Array, AsArray};
+pub use self::external::External;
+pub use self::field_ptr::FieldPtr;
+pub use self::field_root::FieldRoot;
 pub use self::local::Local;
 pub use self::ptr::{Ptr, AsPtr};
 pub use self::root::Root;
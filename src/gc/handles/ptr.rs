@@ -1,8 +1,8 @@
-use gc::{GcHeap, Local, ptr_t};
+use gc::{GcHeap, Local, FieldPtr, ptr_t};
 use std::ops::{Deref, DerefMut};
 use std::marker::PhantomData;
 use std::ptr;
-use std::mem::transmute;
+use std::mem::{size_of, transmute};
 use std::fmt;
 
 pub struct Ptr<T> {
@@ -33,6 +33,16 @@ impl<T> Ptr<T> {
 	pub fn as_local(&self, heap: &GcHeap) -> Local<T> {
 		heap.alloc_local_from_ptr(*self)
 	}
+	
+	/// Returns a reference to the field that `field` selects, e.g.
+	/// `ptr.field(|item| &item.b)`.
+	pub fn field<F, G: FnOnce(&T) -> &F>(&self, field: G) -> FieldPtr<F> {
+		let offset = field(&**self) as *const F as usize - self.ptr as usize;
+		
+		assert!(offset + size_of::<F>() <= size_of::<T>(), "The field is not part of the object");
+		
+		unsafe { FieldPtr::from_base(self.ptr, offset) }
+	}
 }
 
 impl<T> PartialEq for Ptr<T> {
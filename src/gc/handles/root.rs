@@ -1,4 +1,4 @@
-use gc::{Ptr, Local, RootHandles, GcHeap, AsPtr};
+use gc::{Ptr, Local, RootHandles, GcHeap, GcRoot, GcRetainingPath, GcHeapId, AsPtr};
 use std::ops::{Deref, DerefMut};
 use std::marker::PhantomData;
 use std::mem::transmute;
@@ -12,6 +12,8 @@ pub struct Root<T> {
 
 impl<T> Root<T> {
 	pub unsafe fn new<U: AsPtr<T>>(heap: &GcHeap, ptr: U) -> Root<T> {
+		heap.check_owns(ptr.as_ptr().ptr());
+		
 		Root {
 			handles: heap.handles.clone(),
 			handle: heap.handles.add(ptr.as_ptr().ptr()),
@@ -20,8 +22,30 @@ impl<T> Root<T> {
 	}
 	
 	pub fn as_local(&self, heap: &GcHeap) -> Local<T> {
+		debug_assert!(self.handles.heap == heap.id(), "Root belongs to heap {:?} instead of {:?}", self.handles.heap, heap.id());
+		
 		heap.alloc_local_from_ptr(self.as_ptr())
 	}
+	
+	/// Returns the object, or `None` when the heap of the root was dropped.
+	pub fn try_get(&self) -> Option<&T> {
+		unsafe { self.handles.try_get_target(self.handle).map(|ptr| transmute(ptr)) }
+	}
+	
+	pub fn try_get_mut(&mut self) -> Option<&mut T> {
+		unsafe { self.handles.try_get_target(self.handle).map(|ptr| transmute(ptr)) }
+	}
+	
+	/// Returns the heap this root was created from.
+	pub fn heap_id(&self) -> GcHeapId {
+		self.handles.heap
+	}
+	
+	/// Returns the shortest path through which something other than this
+	/// root keeps the object alive.
+	pub fn retaining_path(&self, heap: &GcHeap) -> Option<GcRetainingPath> {
+		heap.find_retaining_path(self.as_ptr().ptr(), Some(GcRoot::Handle(self.handle)))
+	}
 }
 
 impl<T> Deref for Root<T> {
@@ -0,0 +1,143 @@
+// An isolate owns a heap together with the data that references it, so both
+// can be moved to another thread as a unit.
+
+use gc::{GcHeap, GcHeapId, GcWalker, GcOpts, GcListener, GcTriggerInfo, Root, ArrayRoot};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Marks data that may be moved to another thread together with the heap of
+/// an `Isolate`.
+///
+/// Implementing this asserts that everything in the type that is not `Send`
+/// is a handle of a heap, and that `each_heap_id` reports the heap of every
+/// such handle. The isolate checks that these all are its own heap.
+pub unsafe trait IsolateData {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId));
+}
+
+unsafe impl IsolateData for () {
+	fn each_heap_id(&self, _f: &mut FnMut(GcHeapId)) {}
+}
+
+unsafe impl<T> IsolateData for Root<T> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		f(self.heap_id());
+	}
+}
+
+unsafe impl<T> IsolateData for ArrayRoot<T> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		f(self.heap_id());
+	}
+}
+
+unsafe impl<T: IsolateData> IsolateData for Option<T> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		if let Some(ref value) = *self {
+			value.each_heap_id(f);
+		}
+	}
+}
+
+unsafe impl<T: IsolateData> IsolateData for Box<T> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		(**self).each_heap_id(f);
+	}
+}
+
+unsafe impl<T: IsolateData> IsolateData for Vec<T> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		for value in self {
+			value.each_heap_id(f);
+		}
+	}
+}
+
+unsafe impl<K: Eq + Hash + Send, V: IsolateData> IsolateData for HashMap<K, V> {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		for value in self.values() {
+			value.each_heap_id(f);
+		}
+	}
+}
+
+/// A heap and the data that references it, which can be sent to another
+/// thread as a unit. The heap and the data are only accessible through
+/// `enter`, which requires that all local scopes are closed again before it
+/// returns.
+///
+/// Listeners, triggers and the sizing policy of the heap move along with it,
+/// so they must be `Send`. Listeners and triggers are therefore added through
+/// the isolate instead of the heap.
+pub struct Isolate<T: IsolateData> {
+	// The data is declared first so its roots are dropped before the heap.
+	data: T,
+	heap: Box<GcHeap>
+}
+
+// The handles in the data only reference the heap, which moves along with it.
+// Local scopes are not allowed to outlive a call to enter.
+unsafe impl<T: IsolateData> Send for Isolate<T> {}
+
+impl<T: IsolateData> Isolate<T> {
+	/// Creates a heap and initializes the data of the isolate from it.
+	pub fn new<F: FnOnce(&GcHeap) -> T + Send>(walker: Box<GcWalker + Send>, opts: GcOpts, init: F) -> Isolate<T> {
+		let heap = Box::new(GcHeap::new(walker, opts));
+		heap.isolated.set(true);
+		
+		let data = init(&heap);
+		
+		check_no_scopes(&heap);
+		check_data(&heap, &data);
+		
+		Isolate {
+			data: data,
+			heap: heap
+		}
+	}
+	
+	/// Provides access to the heap and the data. Handles created from the
+	/// heap must either be stored in the data or be dropped before `f`
+	/// returns; `f` and its result must be `Send` so handles cannot escape
+	/// through them.
+	///
+	/// Panics when a local scope is still open after `f` returns, or when the
+	/// data holds a handle of another heap.
+	pub fn enter<R: Send, F: FnOnce(&GcHeap, &mut T) -> R + Send>(&mut self, f: F) -> R {
+		let result = f(&self.heap, &mut self.data);
+		
+		check_no_scopes(&self.heap);
+		check_data(&self.heap, &self.data);
+		
+		result
+	}
+	
+	/// Subscribes a listener to GC events of the heap, like
+	/// `GcHeap::add_listener`.
+	pub fn add_listener(&mut self, listener: Box<GcListener + Send>) -> usize {
+		self.heap.push_listener(listener)
+	}
+	
+	pub fn remove_listener(&mut self, id: usize) -> bool {
+		self.heap.remove_listener(id)
+	}
+	
+	/// Sets the trigger of the heap, like `GcHeap::set_gc_trigger`.
+	pub fn set_gc_trigger(&mut self, trigger: Option<Box<Fn(&GcTriggerInfo) -> bool + Send>>) {
+		self.heap.replace_trigger(trigger.map(|trigger| trigger as Box<Fn(&GcTriggerInfo) -> bool>));
+	}
+}
+
+fn check_data<T: IsolateData>(heap: &GcHeap, data: &T) {
+	data.each_heap_id(&mut |id| {
+		if id != heap.id() {
+			panic!("The data of an isolate holds a handle of heap {:?} instead of {:?}", id, heap.id());
+		}
+	});
+}
+
+fn check_no_scopes(heap: &GcHeap) {
+	if heap.scopes.borrow().len() != 0 {
+		panic!("Local scopes must be closed before leaving an isolate");
+	}
+}
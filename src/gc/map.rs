@@ -0,0 +1,316 @@
+// A hash map is a small object that references its storage, an array of
+// entries with open addressing. Entries keep the hash of their key, and keys
+// that are objects are hashed by their identity hash instead of their
+// address, so the map stays valid when the collector moves them.
+
+use gc::{GcHeap, GcMemHeader, Ptr, Root, Local, HEADER_HASH_MAP, ptr_t};
+use std::marker::PhantomData;
+use std::mem::{size_of, transmute, replace};
+use std::ptr;
+
+const EMPTY : u32 = 0;
+const FULL : u32 = 1;
+const DELETED : u32 = 2;
+
+/// Keys of a `GcHashMap`.
+pub trait GcHashKey: Copy {
+	/// Returns the hash of the key. This must not allocate, and must not
+	/// depend on the address of objects.
+	fn hash_key(&self, heap: &GcHeap) -> u32;
+	
+	/// Returns the hash of the key for a lookup, or `None` when no entry can
+	/// have this key. Unlike `hash_key`, this must not assign anything.
+	fn lookup_hash_key(&self, heap: &GcHeap) -> Option<u32> {
+		Some(self.hash_key(heap))
+	}
+	
+	fn eq_key(&self, other: &Self) -> bool;
+}
+
+/// Objects are compared by identity and hashed by their identity hash. An
+/// object without an identity hash cannot be a key yet, so lookups do not
+/// assign one.
+impl<T> GcHashKey for Ptr<T> {
+	fn hash_key(&self, heap: &GcHeap) -> u32 {
+		heap.identity_hash(self.ptr())
+	}
+	
+	fn lookup_hash_key(&self, heap: &GcHeap) -> Option<u32> {
+		heap.identity_hash_if_assigned(self.ptr())
+	}
+	
+	fn eq_key(&self, other: &Ptr<T>) -> bool {
+		self.ptr() == other.ptr()
+	}
+}
+
+macro_rules! int_hash_key {
+	($($ty:ty),*) => {
+		$(
+			impl GcHashKey for $ty {
+				fn hash_key(&self, _: &GcHeap) -> u32 {
+					((*self as u64).wrapping_mul(0x9e3779b97f4a7c15) >> 32) as u32
+				}
+				
+				fn eq_key(&self, other: &$ty) -> bool {
+					*self == *other
+				}
+			}
+		)*
+	}
+}
+
+int_hash_key!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// An entry in the storage of a hash map. The walker for the type id of the
+/// hash map is called for every entry, including unused ones, which are
+/// zeroed. Since the layout starts with the key followed by the value, the
+/// walker can report their pointers as it would for a struct with these two
+/// fields. The words after the value must be reported as `GcWalk::Skip`, or
+/// the walker must return `GcWalk::End`.
+#[repr(C)]
+pub struct GcHashEntry<K, V> {
+	pub key: K,
+	pub value: V,
+	hash: u32,
+	state: u32
+}
+
+/// A hash map in the heap, referenced through `Ptr<GcHashMap<K, V>>`,
+/// `Root<GcHashMap<K, V>>` or `Local<GcHashMap<K, V>>`. When stored in an
+/// object, walkers must report the reference to the hash map as
+/// `GcWalk::Pointer`.
+///
+/// As with `GcVec`, `insert` may allocate after the entry has been stored,
+/// and thereby collect. Other `Ptr`s held by the caller may not survive this.
+pub struct GcHashMap<K, V> {
+	storage: ptr_t,
+	len: usize,
+	/// The number of entries that are not empty, including deleted ones.
+	used: usize,
+	_type: PhantomData<(K, V)>
+}
+
+impl<K: GcHashKey, V> GcHashMap<K, V> {
+	pub fn len(&self) -> usize {
+		self.len
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+	
+	/// Returns the number of entries in the storage. At most three quarters
+	/// of them are used before the storage is replaced.
+	pub fn capacity(&self) -> usize {
+		unsafe { *(self.storage as *const usize) }
+	}
+	
+	pub fn get(&self, heap: &GcHeap, key: K) -> Option<&V> {
+		unsafe {
+			self.lookup(heap, &key).map(|index| &(*self.entries().offset(index as isize)).value)
+		}
+	}
+	
+	pub fn get_mut(&mut self, heap: &GcHeap, key: K) -> Option<&mut V> {
+		unsafe {
+			self.lookup(heap, &key).map(|index| &mut (*self.entries().offset(index as isize)).value)
+		}
+	}
+	
+	pub fn contains_key(&self, heap: &GcHeap, key: K) -> bool {
+		self.get(heap, key).is_some()
+	}
+	
+	/// Inserts a value, returning the value it replaced.
+	pub fn insert(&mut self, heap: &GcHeap, key: K, value: V) -> Option<V> {
+		let hash = key.hash_key(heap);
+		
+		unsafe {
+			let index = match self.find(hash, &key) {
+				Ok(index) => return Some(replace(&mut (*self.entries().offset(index as isize)).value, value)),
+				Err(index) => index
+			};
+			
+			let entry = self.entries().offset(index as isize);
+			
+			if (*entry).state == EMPTY {
+				self.used += 1;
+			}
+			
+			ptr::write(entry, GcHashEntry {
+				key: key,
+				value: value,
+				hash: hash,
+				state: FULL
+			});
+			
+			self.len += 1;
+			
+			// Grow when three quarters of the entries are used, or just rehash when
+			// most of them are deleted.
+			
+			let capacity = self.capacity();
+			if self.used * 4 > capacity * 3 {
+				self.resize(heap, if self.len * 2 > capacity { capacity * 2 } else { capacity });
+			}
+		}
+		
+		None
+	}
+	
+	pub fn remove(&mut self, heap: &GcHeap, key: K) -> Option<V> {
+		unsafe {
+			self.lookup(heap, &key).map(|index| {
+				let entry = self.entries().offset(index as isize);
+				let value = ptr::read(&(*entry).value);
+				
+				// Zero the entry so its key and value are no longer walked.
+				
+				ptr::write_bytes(entry, 0, 1);
+				(*entry).state = DELETED;
+				
+				self.len -= 1;
+				
+				value
+			})
+		}
+	}
+	
+	pub fn clear(&mut self) {
+		unsafe { ptr::write_bytes(self.entries(), 0, self.capacity()) };
+		
+		self.len = 0;
+		self.used = 0;
+	}
+	
+	pub fn iter<'a>(&'a self) -> GcHashMapIter<'a, K, V> {
+		GcHashMapIter {
+			entries: unsafe { self.entries() },
+			index: 0,
+			capacity: self.capacity(),
+			_map: PhantomData
+		}
+	}
+	
+	unsafe fn entries(&self) -> *mut GcHashEntry<K, V> {
+		transmute(self.storage.offset(size_of::<usize>() as isize))
+	}
+	
+	/// Returns the index of the entry of the key, without assigning the key
+	/// a hash.
+	unsafe fn lookup(&self, heap: &GcHeap, key: &K) -> Option<usize> {
+		key.lookup_hash_key(heap).and_then(|hash| self.find(hash, key).ok())
+	}
+	
+	/// Returns the index of the entry of the key, or the index where it
+	/// should be inserted. There is always at least one empty entry.
+	unsafe fn find(&self, hash: u32, key: &K) -> Result<usize, usize> {
+		let entries = self.entries();
+		let mask = self.capacity() - 1;
+		let mut index = hash as usize & mask;
+		let mut deleted = None;
+		
+		loop {
+			let entry = &*entries.offset(index as isize);
+			
+			match entry.state {
+				EMPTY => return Err(deleted.unwrap_or(index)),
+				DELETED => if deleted.is_none() {
+					deleted = Some(index);
+				},
+				_ => if entry.hash == hash && entry.key.eq_key(key) {
+					return Ok(index);
+				}
+			}
+			
+			index = (index + 1) & mask;
+		}
+	}
+	
+	/// Replaces the storage with one of `capacity` entries, dropping the
+	/// deleted entries.
+	unsafe fn resize(&mut self, heap: &GcHeap, capacity: usize) {
+		// The allocation may move this hash map, so find it back through a root.
+		
+		let ty = GcMemHeader::from_ptr(self.storage).get_type_id();
+		let handle = heap.handles.add(self as *const GcHashMap<K, V> as ptr_t);
+		
+		let storage = heap.alloc_hash_map_storage::<K, V>(ty, capacity);
+		
+		let this = &mut *(heap.handles.remove(handle) as *mut GcHashMap<K, V>);
+		let entries = this.entries();
+		let old_capacity = this.capacity();
+		
+		this.storage = storage;
+		this.used = this.len;
+		
+		for i in 0..old_capacity {
+			let entry = entries.offset(i as isize);
+			
+			if (*entry).state == FULL {
+				let index = this.find((*entry).hash, &(*entry).key).unwrap_err();
+				
+				ptr::copy_nonoverlapping(entry, this.entries().offset(index as isize), 1);
+			}
+		}
+	}
+}
+
+/// Iterates over the keys and values of a hash map in no particular order.
+pub struct GcHashMapIter<'a, K: 'a, V: 'a> {
+	entries: *const GcHashEntry<K, V>,
+	index: usize,
+	capacity: usize,
+	_map: PhantomData<&'a GcHashMap<K, V>>
+}
+
+impl<'a, K, V> Iterator for GcHashMapIter<'a, K, V> {
+	type Item = (&'a K, &'a V);
+	
+	fn next(&mut self) -> Option<(&'a K, &'a V)> {
+		while self.index < self.capacity {
+			let entry = unsafe { &*self.entries.offset(self.index as isize) };
+			
+			self.index += 1;
+			
+			if entry.state == FULL {
+				return Some((&entry.key, &entry.value));
+			}
+		}
+		
+		None
+	}
+}
+
+impl GcHeap {
+	/// Allocates an empty hash map with room for at least `capacity` entries.
+	/// The walker is called for the entries with type id `ty`, as described
+	/// for `GcHashEntry`. The hash map itself also has this type id, but is
+	/// walked by the collector.
+	pub unsafe fn alloc_hash_map<K: GcHashKey, V>(&self, ty: u32, capacity: usize) -> Ptr<GcHashMap<K, V>> {
+		let map = self.alloc_with::<GcHashMap<K, V>>(ty, HEADER_HASH_MAP);
+		
+		// Keep the hash map alive while its storage is allocated.
+		
+		let handle = self.handles.add(map.ptr());
+		let storage = self.alloc_hash_map_storage::<K, V>(ty, (capacity * 4 / 3 + 1).next_power_of_two().max(4));
+		let mut map = Ptr::<GcHashMap<K, V>>::from_ptr(self.handles.remove(handle));
+		
+		map.storage = storage;
+		
+		map
+	}
+	
+	pub fn alloc_hash_map_root<K: GcHashKey, V>(&self, ty: u32, capacity: usize) -> Root<GcHashMap<K, V>> {
+		unsafe { Root::new(self, self.alloc_hash_map::<K, V>(ty, capacity)) }
+	}
+	
+	pub fn alloc_hash_map_local<K: GcHashKey, V>(&self, ty: u32, capacity: usize) -> Local<GcHashMap<K, V>> {
+		self.alloc_local_from_ptr(unsafe { self.alloc_hash_map::<K, V>(ty, capacity) })
+	}
+	
+	unsafe fn alloc_hash_map_storage<K, V>(&self, ty: u32, capacity: usize) -> ptr_t {
+		self.alloc_array_with::<GcHashEntry<K, V>>(ty, size_of::<GcHashEntry<K, V>>(), capacity, 0).ptr()
+	}
+}
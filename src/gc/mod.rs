@@ -4,26 +4,72 @@
 
 const INITIAL_LOCAL_SCOPE_CAPACITY : usize = 8;
 
+// Fraction of the heap that must have been allocated since the last
+// collection before collect_if_worthwhile collects.
+const IDLE_GC_THRESHOLD : f64 = 0.25;
+
 extern crate libc;
 extern crate time;
 
 use std::ops::Index;
 use std::ptr;
 use std::mem::{size_of, transmute, swap};
-use std::cell::RefCell;
-use self::strategy::Strategy;
-use self::strategy::copying::Copying;
+use std::cmp::min;
+use std::hint::black_box;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::io::Write;
+use self::strategy::{Strategy, GcContext};
+use self::strategy::copying::{Copying, AllocBuffer, ALLOC_BUFFER_SIZE};
+use self::shared::SharedHeap;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 pub use self::handles::{ArrayLocal, ArrayRoot, Array, Local, Ptr, Root};
 pub use self::handles::{AsPtr, AsArray};
+pub use self::handles::{External, FieldPtr, FieldRoot};
+pub use self::events::{GcListener, GcReason, GcKind, GcRequest, GcTriggerInfo, GcCollectionStats, GcHeapStats, GcLeakedRoot};
+pub use self::census::{GcCensus, GcTypeCensus};
+pub use self::graph::{GcRoot, GcObject, GcGraphVisitor, GcEdge, GcRetainingPath};
+pub use self::snapshot::GcSnapshotNames;
+pub use self::profiler::{GcProfiler, GcProfilerOpts, GcSample};
+pub use self::tracer::GcTracer;
+pub use self::opts::{GcOpts, GcOptsBuilder, GcOptsError};
+pub use self::sizing::{HeapSizingPolicy, HeapSizingInfo, GrowingHeapSizing, FixedHeapSizing, OccupancyHeapSizing};
+pub use self::shared::SharedGcHeap;
+pub use self::isolate::{Isolate, IsolateData};
+pub use self::string::{GcString, GcStringUnits};
+pub use self::vec::GcVec;
+pub use self::map::{GcHashMap, GcHashMapIter, GcHashKey, GcHashEntry};
 
 pub mod os;
 mod strategy;
 pub mod handles;
+pub mod events;
+pub mod census;
+pub mod graph;
+pub mod snapshot;
+pub mod dot;
+pub mod profiler;
+pub mod tracer;
+pub mod sizing;
+pub mod opts;
+mod shared;
+pub mod isolate;
+pub mod string;
+pub mod vec;
+pub mod map;
 
 #[allow(non_camel_case_types)] 
 pub type ptr_t = *const u8;
 
+static NEXT_HEAP_ID : AtomicUsize = AtomicUsize::new(1);
+
+/// Identifies a `GcHeap`. Every mutator of a shared heap has its own id,
+/// because root handles belong to the mutator they were created from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GcHeapId(usize);
+
 #[macro_export]
 macro_rules! field_offset {
 	( $ty:ty, $ident:ident ) => {
@@ -45,6 +91,18 @@ impl Drop for LocalScope {
 	}
 }
 
+struct SafeRegionGuard<'a> {
+	shared: &'a SharedHeap,
+	heap: &'a GcHeap
+}
+
+impl<'a> Drop for SafeRegionGuard<'a> {
+	fn drop(&mut self) {
+		self.shared.leave_safe_region(self.heap);
+		self.heap.stack.set(None);
+	}
+}
+
 struct LocalScopeData {
 	current: Vec<ptr_t>,
 	handles: Vec<Vec<ptr_t>>
@@ -69,6 +127,10 @@ impl LocalScopeData {
 		unsafe { (*self.current).as_ptr().offset(index as isize) }
 	}
 	
+	fn len(&self) -> usize {
+		self.handles.iter().fold(self.current.len(), |len, handles| len + handles.len())
+	}
+	
 	fn grow(&mut self) {
 		let mut new = Vec::with_capacity(self.current.capacity() * 2);
 		swap(&mut new, &mut self.current);
@@ -76,37 +138,31 @@ impl LocalScopeData {
 	}
 }
 
-pub struct GcOpts {
-	pub initial_heap: usize,
-	pub slow_growth_factor: f64,
-	pub fast_growth_factor: f64
-}
-
-impl GcOpts {
-	pub fn default() -> GcOpts {
-		GcOpts {
-			initial_heap: 16 * 1024 * 1024, // 16M
-			slow_growth_factor: 1.5f64,
-			fast_growth_factor: 3f64
-		}
-	}
-}
-
 struct RootHandles {
+	heap: GcHeapId,
 	data: RefCell<RootHandlesData>
 }
 
 struct RootHandlesData {
 	ptrs: Vec<ptr_t>,
-	free: Vec<u32>
+	/// Incremented whenever a handle is removed, so stale copies of an
+	/// `External` are detected when the handle is reused.
+	generations: Vec<u32>,
+	free: Vec<u32>,
+	/// Set when the heap is dropped. The handles of the roots that are still
+	/// alive remain allocated, but are not valid anymore.
+	dropped: bool
 }
 
 impl RootHandles {
 	fn new() -> RootHandles {
 		RootHandles {
+			heap: GcHeapId(NEXT_HEAP_ID.fetch_add(1, Ordering::Relaxed)),
 			data: RefCell::new(RootHandlesData {
 				ptrs: Vec::new(),
-				free: Vec::new()
+				generations: Vec::new(),
+				free: Vec::new(),
+				dropped: false
 			})
 		}
 	}
@@ -122,6 +178,7 @@ impl RootHandles {
 		} else {
 			let index = data.ptrs.len() as u32;
 			data.ptrs.push(ptr);
+			data.generations.push(0);
 			index
 		};
 		
@@ -131,21 +188,50 @@ impl RootHandles {
 	fn remove(&self, handle: u32) -> ptr_t {
 		let mut data = self.data.borrow_mut();
 		
+		if data.dropped {
+			return ptr::null();
+		}
+		
 		data.free.push(handle);
 		let ptr = data.ptrs[handle as usize];
 		data.ptrs[handle as usize] = ptr::null();
+		data.generations[handle as usize] = data.generations[handle as usize].wrapping_add(1);
 		
 		ptr
 	}
 	
+	fn generation(&self, handle: u32) -> u32 {
+		self.data.borrow().generations[handle as usize]
+	}
+	
 	fn clone_root(&self, handle: u32) -> u32 {
+		// A clone of a root of a dropped heap is just as invalid.
+		
+		if self.data.borrow().dropped {
+			return handle;
+		}
+		
 		let ptr = self.data.borrow().ptrs[handle as usize];
 		self.add(ptr)
 	}
 	
+	unsafe fn try_get_target(&self, handle: u32) -> Option<ptr_t> {
+		let data = &*self.data.borrow();
+		
+		if data.dropped || data.ptrs.len() <= handle as usize {
+			None
+		} else {
+			Some(data.ptrs[handle as usize])
+		}
+	}
+	
 	unsafe fn get_target(&self, handle: u32) -> ptr_t {
 		let data = &*self.data.borrow();
 		
+		if data.dropped {
+			panic!("Root used after its GcHeap was dropped");
+		}
+		
 		if data.ptrs.len() <= handle as usize {
 			panic!("Root is not valid anymore");
 		}
@@ -154,36 +240,73 @@ impl RootHandles {
 	}
 }
 
+/// The largest size of an object, or of an item of an array.
+const MAX_OBJECT_SIZE : usize = 0xffffffff;
+
+const HEADER_ARRAY : u32 = 1 << 8;
+/// The object does not contain any pointers.
+const HEADER_PLAIN : u32 = 1 << 9;
+/// The object is a `GcVec`, which only references its storage. On an array,
+/// the array is the storage of a `GcVec`. The capacity is stored in place of
+/// the length of the array, followed by the number of initialized items.
+const HEADER_VECTOR : u32 = 1 << 10;
+/// The array holds the code units of a `GcString`.
+const HEADER_STRING : u32 = 1 << 11;
+/// The object is a `GcHashMap`, which only references its storage.
+const HEADER_HASH_MAP : u32 = 1 << 12;
+
+/// Bits 0 to 6 of `header` hold the type id and the higher bits the flags.
+/// The size has a field of its own, so on 64-bit targets the header still
+/// takes a single word, with the size in its upper half.
 struct GcMemHeader {
-	header: usize
+	header: u32,
+	/// The size of the object, or of an item of the array.
+	size: u32
 }
 
 impl GcMemHeader {
-	fn new(ty: u32, size: usize, is_array: bool) -> GcMemHeader {
-		let mut header =
-			(ty & 0x7f) << 1 |
-			(size as u32 & 0xffffff) << 8;
-		
-		if is_array {
-			header |= 1;
-		}
+	fn new(ty: u32, size: usize, flags: u32) -> GcMemHeader {
+		assert!(size <= MAX_OBJECT_SIZE, "Objects and array items must not be larger than {} bytes", MAX_OBJECT_SIZE);
 		
 		GcMemHeader {
-			header: header as usize
+			header: flags | ty & 0x7f,
+			size: size as u32
 		}
 	}
 	
 	#[inline(always)]
 	fn get_type_id(&self) -> u32 {
-		(self.header >> 1) as u32 & 0x7f
+		self.header & 0x7f
 	}
 	
 	fn get_size(&self) -> usize {
-		self.header >> 8 & 0xffffff
+		self.size as usize
 	}
 	
 	fn is_array(&self) -> bool {
-		self.header & 1 != 0
+		self.header & HEADER_ARRAY != 0
+	}
+	
+	/// Returns whether the walker must be called for the object.
+	fn has_pointers(&self) -> bool {
+		self.header & HEADER_PLAIN == 0
+	}
+	
+	fn is_vector(&self) -> bool {
+		self.header & HEADER_VECTOR != 0
+	}
+	
+	fn is_string(&self) -> bool {
+		self.header & HEADER_STRING != 0
+	}
+	
+	fn is_hash_map(&self) -> bool {
+		self.header & HEADER_HASH_MAP != 0
+	}
+	
+	/// Returns the offset of the first item of an array.
+	fn items_offset(&self) -> usize {
+		if self.is_vector() { 2 * size_of::<usize>() } else { size_of::<usize>() }
 	}
 	
 	unsafe fn from_ptr<'a>(ptr: ptr_t) -> &'a mut GcMemHeader {
@@ -191,59 +314,429 @@ impl GcMemHeader {
 	}
 }
 
+enum GcBackend {
+	Local(RefCell<Copying>, Box<GcWalker>),
+	Shared(Arc<SharedHeap>)
+}
+
 pub struct GcHeap {
 	handles: Rc<RootHandles>,
-	heap: RefCell<Copying>,
+	backend: GcBackend,
+	buffer: Cell<AllocBuffer>,
 	scopes: RefCell<Vec<LocalScopeData>>,
-	walker: Box<GcWalker>
+	listeners: RefCell<Vec<(usize, Box<GcListener>)>>,
+	next_listener: Cell<usize>,
+	allocated: Cell<usize>,
+	totals: RefCell<GcHeapStats>,
+	census_on_gc: Cell<bool>,
+	last_census: RefCell<Option<GcCensus>>,
+	profiler: RefCell<Option<GcProfiler>>,
+	next_sample: Cell<usize>,
+	tracer: RefCell<Option<GcTracer>>,
+	allocation_budget: Option<usize>,
+	allocated_at_gc: Cell<usize>,
+	next_budget_gc: Cell<usize>,
+	trigger: RefCell<Option<Box<Fn(&GcTriggerInfo) -> bool>>>,
+	has_trigger: Cell<bool>,
+	/// Set when the heap is owned by an `Isolate`, which requires listeners
+	/// and triggers to be `Send`.
+	isolated: Cell<bool>,
+	/// Allocations take the slow path once `allocated` reaches this limit, so
+	/// the budget, trigger and profiler are only checked when needed.
+	alloc_limit: Cell<usize>,
+	conservative_stack: bool,
+	/// The part of the stack of a parked mutator that is scanned when the
+	/// stack is scanned conservatively.
+	stack: Cell<Option<(ptr_t, ptr_t)>>
 }
 
 impl GcHeap {
 	pub fn new(walker: Box<GcWalker>, opts: GcOpts) -> GcHeap {
-		if opts.fast_growth_factor <= 1f64 {
-			panic!("fast_growth_factor must be more than 1");
-		}
-		if opts.slow_growth_factor <= 1f64 {
-			panic!("slow_growth_factor must be more than 1");
+		if let Err(error) = opts.validate() {
+			panic!("{}", error);
 		}
 		
+		let allocation_budget = opts.allocation_budget;
+		let conservative_stack = opts.conservative_stack;
+		
+		GcHeap::with_backend(GcBackend::Local(RefCell::new(Copying::new(opts)), walker), allocation_budget, conservative_stack)
+	}
+	
+	fn from_shared(shared: Arc<SharedHeap>) -> GcHeap {
+		shared.register();
+		
+		let allocation_budget = shared.allocation_budget;
+		let conservative_stack = shared.conservative_stack;
+		
+		GcHeap::with_backend(GcBackend::Shared(shared), allocation_budget, conservative_stack)
+	}
+	
+	fn with_backend(backend: GcBackend, allocation_budget: Option<usize>, conservative_stack: bool) -> GcHeap {
 		GcHeap {
 			handles: Rc::new(RootHandles::new()),
-			heap: RefCell::new(Copying::new(opts)),
+			backend: backend,
+			buffer: Cell::new(AllocBuffer::empty()),
 			scopes: RefCell::new(Vec::new()),
-			walker: walker
+			listeners: RefCell::new(Vec::new()),
+			next_listener: Cell::new(0),
+			allocated: Cell::new(0),
+			totals: RefCell::new(GcHeapStats::new()),
+			census_on_gc: Cell::new(false),
+			last_census: RefCell::new(None),
+			profiler: RefCell::new(None),
+			next_sample: Cell::new(usize::max_value()),
+			tracer: RefCell::new(None),
+			allocation_budget: allocation_budget,
+			allocated_at_gc: Cell::new(0),
+			next_budget_gc: Cell::new(allocation_budget.unwrap_or(usize::max_value())),
+			trigger: RefCell::new(None),
+			has_trigger: Cell::new(false),
+			isolated: Cell::new(false),
+			alloc_limit: Cell::new(allocation_budget.unwrap_or(usize::max_value())),
+			conservative_stack: conservative_stack,
+			stack: Cell::new(None)
+		}
+	}
+	
+	pub fn id(&self) -> GcHeapId {
+		self.handles.heap
+	}
+	
+	/// Returns whether the object was allocated from this heap.
+	pub fn contains(&self, ptr: ptr_t) -> bool {
+		self.with_strategy(|heap| heap.contains(ptr))
+	}
+	
+	/// Returns a hash for the identity of an object. Unlike its address, the
+	/// hash does not change when the object is moved by a collection. It is
+	/// assigned on first use, and is never 0 except for null.
+	pub fn identity_hash(&self, ptr: ptr_t) -> u32 {
+		if ptr.is_null() {
+			return 0;
+		}
+		
+		self.check_owns(ptr);
+		
+		self.with_strategy(|heap| heap.identity_hash(ptr))
+	}
+	
+	/// Returns the identity hash of an object if it was assigned one, without
+	/// assigning one otherwise.
+	pub fn identity_hash_if_assigned(&self, ptr: ptr_t) -> Option<u32> {
+		if ptr.is_null() {
+			return Some(0);
+		}
+		
+		self.check_owns(ptr);
+		
+		self.with_strategy(|heap| heap.identity_hash_if_assigned(ptr))
+	}
+	
+	/// Verifies in debug builds that a pointer that is about to be registered
+	/// in a handle references an object of this heap.
+	#[inline(always)]
+	fn check_owns(&self, ptr: ptr_t) {
+		if cfg!(debug_assertions) && !ptr.is_null() && !self.contains(ptr) {
+			panic!("Object {:?} does not belong to heap {:?}; use an External to reference objects of other heaps", ptr, self.id());
+		}
+	}
+	
+	fn update_alloc_limit(&self) {
+		let limit = if self.has_trigger.get() {
+			0
+		} else {
+			min(self.next_budget_gc.get(), self.next_sample.get())
+		};
+		
+		self.alloc_limit.set(limit);
+	}
+	
+	/// Allocates from the allocation buffer of the heap. This is inlined into
+	/// the callers; everything else is left to `alloc_raw_slow`.
+	#[inline(always)]
+	unsafe fn alloc_raw(&self, ty: u32, size: usize) -> ptr_t {
+		let allocated = self.allocated.get() + size;
+		
+		if allocated < self.alloc_limit.get() && !self.is_safepoint_requested() {
+			let mut buffer = self.buffer.get();
+			
+			let ptr = buffer.alloc(size);
+			if !ptr.is_null() {
+				self.buffer.set(buffer);
+				self.allocated.set(allocated);
+				
+				return ptr.offset(size_of::<GcMemHeader>() as isize);
+			}
 		}
+		
+		self.alloc_raw_slow(ty, size)
 	}
 	
-	unsafe fn alloc_raw(&self, size: usize) -> ptr_t {
-		let mut ptr = self.heap.borrow_mut().alloc_raw(size);
+	#[inline(never)]
+	unsafe fn alloc_raw_slow(&self, ty: u32, size: usize) -> ptr_t {
+		if self.allocated.get() >= self.next_budget_gc.get() {
+			self.gc_with(GcRequest::new(GcKind::Major, GcReason::AllocationBudget));
+		} else if self.has_trigger.get() && self.is_triggered() {
+			self.gc_with(GcRequest::new(GcKind::Major, GcReason::Trigger));
+		}
+		
+		let mut ptr = self.alloc_from_strategy(size);
 		if ptr.is_null() {
-			self.gc();
+			// Retry while the world is still stopped, so the other mutators of a
+			// shared heap cannot take the memory that was just freed.
+			
+			ptr = self.with_world_stopped(|mutators| {
+				self.collect(GcRequest::new(GcKind::Major, GcReason::AllocationFailed), mutators);
+				self.alloc_from_buffer(size)
+			});
 			
-			ptr = self.heap.borrow_mut().alloc_raw(size);
 			if ptr.is_null() {
 				panic!("Could not allocate memory after GC");
 			}
 		}
 		
-		if ptr.is_null() {
-			ptr
-		} else {
-			ptr.offset(size_of::<GcMemHeader>() as isize)
+		let ptr = ptr.offset(size_of::<GcMemHeader>() as isize);
+		
+		let allocated = self.allocated.get() + size;
+		self.allocated.set(allocated);
+		
+		if allocated >= self.next_sample.get() {
+			self.sample(ptr, ty, size);
 		}
+		
+		ptr
 	}
 	
-	pub unsafe fn alloc<T>(&self, ty: u32) -> Ptr<T> {
-		let size = (size_of::<T>() + size_of::<usize>() - 1) / size_of::<usize>() * size_of::<usize>();
+	unsafe fn alloc_from_strategy(&self, size: usize) -> ptr_t {
+		self.safepoint();
+		self.alloc_from_buffer(size)
+	}
+	
+	unsafe fn alloc_from_buffer(&self, size: usize) -> ptr_t {
+		let mut buffer = self.buffer.get();
 		
-		let ptr = self.alloc_raw(
-			size +
-			size_of::<GcMemHeader>()
-		);
+		let ptr = buffer.alloc(size);
+		if !ptr.is_null() {
+			self.buffer.set(buffer);
+			return ptr;
+		}
 		
-		*GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, size, false);
+		// Large objects are allocated directly so they don't waste the remainder
+		// of the buffer.
 		
-		Ptr::from_ptr(ptr)
+		if size > ALLOC_BUFFER_SIZE / 4 {
+			return self.with_strategy(|heap| heap.alloc_raw(size));
+		}
+		
+		if !self.with_strategy(|heap| heap.refill(&mut buffer, size)) {
+			return ptr::null();
+		}
+		
+		let ptr = buffer.alloc(size);
+		self.buffer.set(buffer);
+		
+		ptr
+	}
+	
+	fn with_strategy<R, F: FnOnce(&mut Copying) -> R>(&self, f: F) -> R {
+		match self.backend {
+			GcBackend::Local(ref heap, _) => f(&mut *heap.borrow_mut()),
+			GcBackend::Shared(ref shared) => f(&mut *shared.heap.lock().unwrap())
+		}
+	}
+	
+	fn walker(&self) -> &GcWalker {
+		match self.backend {
+			GcBackend::Local(_, ref walker) => &**walker,
+			GcBackend::Shared(ref shared) => &*shared.walker
+		}
+	}
+	
+	/// Calls `f` with all mutators of the heap while none of them are running.
+	fn with_world_stopped<R, F: FnOnce(&[&GcHeap]) -> R>(&self, f: F) -> R {
+		match self.backend {
+			GcBackend::Local(..) => f(&[self]),
+			GcBackend::Shared(ref shared) => shared.stop_the_world(self, f)
+		}
+	}
+	
+	#[inline(always)]
+	fn is_safepoint_requested(&self) -> bool {
+		match self.backend {
+			GcBackend::Local(..) => false,
+			GcBackend::Shared(ref shared) => shared.is_requested()
+		}
+	}
+	
+	/// Parks the thread when another thread of a shared heap is waiting to
+	/// collect. Long running code that does not allocate must call this
+	/// regularly. This does nothing for a heap that is not shared.
+	pub fn safepoint(&self) {
+		if self.is_safepoint_requested() {
+			if let GcBackend::Shared(ref shared) = self.backend {
+				shared.safepoint(self);
+			}
+		}
+	}
+	
+	/// Runs `f` while allowing other threads of a shared heap to collect,
+	/// e.g. around blocking I/O. This is unsafe because `f` must not access
+	/// the heap or any of its handles, which another thread may be updating.
+	pub unsafe fn safe_region<R, F: FnOnce() -> R>(&self, f: F) -> R {
+		let shared = match self.backend {
+			GcBackend::Local(..) => return f(),
+			GcBackend::Shared(ref shared) => shared
+		};
+		
+		self.with_stack(|stack| {
+			self.stack.set(stack);
+			shared.enter_safe_region(self);
+			
+			let _guard = SafeRegionGuard {
+				shared: &**shared,
+				heap: self
+			};
+			
+			f()
+		})
+	}
+	
+	/// Calls `f` with the part of the stack of the current thread that
+	/// belongs to the callers of this function, when the stack is scanned
+	/// conservatively.
+	#[inline(never)]
+	fn with_stack<R, F: FnOnce(Option<(ptr_t, ptr_t)>) -> R>(&self, f: F) -> R {
+		if !self.conservative_stack {
+			return f(None);
+		}
+		
+		let mut registers = [0; os::SPILLED_REGISTERS];
+		os::spill_registers(&mut registers);
+		
+		let result = f(Some((registers.as_ptr() as ptr_t, os::stack_base())));
+		
+		// Keep the spilled registers on the stack until here.
+		black_box(&registers);
+		
+		result
+	}
+	
+	fn trigger_info(&self) -> GcTriggerInfo {
+		GcTriggerInfo {
+			allocated_since_gc: self.allocated.get() - self.allocated_at_gc.get(),
+			mem_allocated: self.mem_allocated(),
+			mem_used: self.mem_used()
+		}
+	}
+	
+	fn is_triggered(&self) -> bool {
+		let info = self.trigger_info();
+		
+		match *self.trigger.borrow() {
+			Some(ref trigger) => trigger(&info),
+			None => false
+		}
+	}
+	
+	/// Sets a predicate that is consulted on every allocation and requests a
+	/// collection by returning true.
+	///
+	/// Panics when the heap belongs to an `Isolate`; use
+	/// `Isolate::set_gc_trigger` instead.
+	pub fn set_gc_trigger(&self, trigger: Option<Box<Fn(&GcTriggerInfo) -> bool>>) {
+		if self.isolated.get() {
+			panic!("The trigger of an isolated heap must be set through Isolate::set_gc_trigger");
+		}
+		
+		self.replace_trigger(trigger);
+	}
+	
+	fn replace_trigger(&self, trigger: Option<Box<Fn(&GcTriggerInfo) -> bool>>) {
+		self.has_trigger.set(trigger.is_some());
+		*self.trigger.borrow_mut() = trigger;
+		self.update_alloc_limit();
+	}
+	
+	/// Collects when enough has been allocated since the last collection to
+	/// make it worthwhile and the collection is expected to finish before the
+	/// deadline, which is expressed in `time::precise_time_ns`. Returns whether
+	/// a collection was performed. Meant to be called from an event loop
+	/// between tasks.
+	pub fn collect_if_worthwhile(&self, deadline_ns: u64) -> bool {
+		let info = self.trigger_info();
+		
+		if (info.allocated_since_gc as f64) < info.mem_allocated as f64 * IDLE_GC_THRESHOLD {
+			return false;
+		}
+		
+		// Estimate the pause from the average of the previous collections.
+		
+		let expected_pause_ns = {
+			let totals = self.totals.borrow();
+			
+			if totals.collections > 0 {
+				totals.total_pause_ns / totals.collections as u64
+			} else {
+				0
+			}
+		};
+		
+		if time::precise_time_ns() + expected_pause_ns > deadline_ns {
+			return false;
+		}
+		
+		self.gc_with(GcRequest::new(GcKind::Minor, GcReason::Idle));
+		
+		true
+	}
+	
+	fn sample(&self, ptr: ptr_t, ty: u32, size: usize) {
+		if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+			profiler.sample(ptr, ty, size);
+			
+			// Skip over allocations larger than the interval.
+			
+			let mut next_sample = self.next_sample.get();
+			while next_sample <= self.allocated.get() {
+				next_sample += profiler.interval();
+			}
+			self.next_sample.set(next_sample);
+			self.update_alloc_limit();
+		}
+	}
+	
+	/// Starts sampling allocations. Any previous profile is discarded.
+	pub fn start_profiling(&self, opts: GcProfilerOpts) {
+		let profiler = GcProfiler::new(opts);
+		
+		self.next_sample.set(self.allocated.get() + profiler.interval());
+		self.update_alloc_limit();
+		*self.profiler.borrow_mut() = Some(profiler);
+	}
+	
+	pub fn stop_profiling(&self) -> Option<GcProfiler> {
+		self.next_sample.set(usize::max_value());
+		self.update_alloc_limit();
+		self.profiler.borrow_mut().take()
+	}
+	
+	/// Sets the tag that is recorded for allocations sampled from now on.
+	/// When no tag is set, a backtrace is captured if enabled.
+	pub fn set_allocation_tag(&self, tag: Option<String>) {
+		if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+			profiler.set_tag(tag);
+		}
+	}
+	
+	pub fn write_profile_report(&self, writer: &mut Write) -> io::Result<()> {
+		match *self.profiler.borrow() {
+			Some(ref profiler) => profiler.write_report(writer),
+			None => Ok(())
+		}
+	}
+	
+	pub unsafe fn alloc<T>(&self, ty: u32) -> Ptr<T> {
+		self.alloc_with::<T>(ty, 0)
 	}
 	
 	pub fn alloc_root<T>(&self, ty: u32) -> Root<T> {
@@ -254,7 +747,24 @@ impl GcHeap {
 		self.alloc_local_from_ptr(unsafe { self.alloc::<T>(ty) })
 	}
 	
+	/// Allocates an object with `flags` set in the header.
+	unsafe fn alloc_with<T>(&self, ty: u32, flags: u32) -> Ptr<T> {
+		let size = (size_of::<T>() + size_of::<usize>() - 1) / size_of::<usize>() * size_of::<usize>();
+		
+		let ptr = self.alloc_raw(
+			ty,
+			size +
+			size_of::<GcMemHeader>()
+		);
+		
+		*GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, size, flags);
+		
+		Ptr::from_ptr(ptr)
+	}
+	
 	fn alloc_local_from_ptr<T, U: AsPtr<T>>(&self, ptr: U) -> Local<T> {
+		self.check_owns(ptr.as_ptr().ptr());
+		
 		let mut scopes = self.scopes.borrow_mut();
 		let len = scopes.len();
 		if len == 0 {
@@ -273,6 +783,8 @@ impl GcHeap {
 	}
 	
 	fn alloc_array_local_from_ptr<T, U: AsArray<T>>(&self, ptr: U) -> ArrayLocal<T> {
+		self.check_owns(ptr.as_ptr().ptr());
+		
 		let mut scopes = self.scopes.borrow_mut();
 		let len = scopes.len();
 		if len == 0 {
@@ -282,58 +794,315 @@ impl GcHeap {
 		unsafe { ArrayLocal::new(transmute(scopes[len - 1].add(ptr.as_ptr().ptr()))) }
 	}
 	
+	/// Allocates an array with the items at a stride of `size_of::<T>()`, the
+	/// same as a slice. The walker is called for every word of every item, so
+	/// items that are not a whole number of words are never walked.
 	pub unsafe fn alloc_array<T>(&self, ty: u32, size: usize) -> Array<T> {
-		let item_size = (size_of::<T>() + size_of::<usize>() - 1) / size_of::<usize>() * size_of::<usize>();
-		
+		self.alloc_array_with(ty, size_of::<T>(), size, 0)
+	}
+	
+	/// Allocates an array of items that do not contain any pointers. The
+	/// items are never walked, so storing a `Ptr` in them is not allowed.
+	pub unsafe fn alloc_array_plain<T: Copy>(&self, ty: u32, size: usize) -> Array<T> {
+		self.alloc_array_with(ty, size_of::<T>(), size, HEADER_PLAIN)
+	}
+	
+	/// This is unsafe because `T` must not contain any pointers, which `Copy`
+	/// does not guarantee.
+	pub unsafe fn alloc_array_plain_root<T: Copy>(&self, ty: u32, size: usize) -> ArrayRoot<T> {
+		ArrayRoot::new(self, self.alloc_array_plain::<T>(ty, size))
+	}
+	
+	/// This is unsafe because `T` must not contain any pointers, which `Copy`
+	/// does not guarantee.
+	pub unsafe fn alloc_array_plain_local<T: Copy>(&self, ty: u32, size: usize) -> ArrayLocal<T> {
+		self.alloc_array_local_from_ptr(self.alloc_array_plain::<T>(ty, size))
+	}
+	
+	/// Allocates a zeroed byte buffer, e.g. for the contents of a string or a
+	/// typed array.
+	pub unsafe fn alloc_bytes(&self, ty: u32, len: usize) -> Array<u8> {
+		self.alloc_array_plain::<u8>(ty, len)
+	}
+	
+	pub fn alloc_bytes_root(&self, ty: u32, len: usize) -> ArrayRoot<u8> {
+		unsafe { self.alloc_array_plain_root::<u8>(ty, len) }
+	}
+	
+	pub fn alloc_bytes_local(&self, ty: u32, len: usize) -> ArrayLocal<u8> {
+		unsafe { self.alloc_array_plain_local::<u8>(ty, len) }
+	}
+	
+	/// Allocates an array with `flags` set in the header next to `HEADER_ARRAY`.
+	unsafe fn alloc_array_with<T>(&self, ty: u32, item_size: usize, size: usize, flags: u32) -> Array<T> {
 		let ptr = self.alloc_raw(
+			ty,
 			size_of::<usize>() +
 			(item_size * size) +
 			size_of::<GcMemHeader>()
 		);
 		
-		*GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, item_size, true);
+		*GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, item_size, HEADER_ARRAY | flags);
 		*transmute::<_, *mut usize>(ptr) = size;
 		
 		Array::from_ptr(ptr)
 	}
 	
 	pub fn gc(&self) {
+		self.gc_with(GcRequest::new(GcKind::Major, GcReason::Requested));
+	}
+	
+	pub fn gc_with(&self, request: GcRequest) {
+		self.with_world_stopped(|mutators| self.collect(request, mutators));
+	}
+	
+	/// Collects with the world stopped. `mutators` starts with this heap.
+	fn collect(&self, request: GcRequest, mutators: &[&GcHeap]) {
+		for &(_, ref listener) in &*self.listeners.borrow() {
+			listener.before_gc(&request);
+		}
+		
+		let mut stats = GcCollectionStats::new(&request);
+		stats.kind = self.with_strategy(|heap| heap.collection_kind(request.kind));
+		stats.bytes_before = self.mem_used();
+		stats.heap_size_before = self.mem_allocated();
+		
+		let start = time::precise_time_ns();
+		
+		if let Some(ref mut tracer) = *self.tracer.borrow_mut() {
+			tracer.begin("gc");
+			tracer.set_args(format!("\"reason\":\"{:?}\",\"kind\":\"{:?}\"", request.reason, stats.kind));
+		}
+		
+		self.with_stack(|stack| self.collect_walkers(&mut stats, mutators, stack));
+		
+		self.with_strategy(|heap| {
+			for mutator in mutators {
+				mutator.update_after_gc(heap);
+			}
+			
+			heap.after_gc();
+		});
+		
+		if let Some(ref mut tracer) = *self.tracer.borrow_mut() {
+			tracer.end();
+		}
+		
+		stats.pause_ns = time::precise_time_ns() - start;
+		stats.bytes_after = self.mem_used();
+		stats.heap_size_after = self.mem_allocated();
+		
+		{
+			let mut totals = self.totals.borrow_mut();
+			
+			totals.collections += 1;
+			match stats.kind {
+				GcKind::Minor => totals.minor_collections += 1,
+				GcKind::Major => totals.major_collections += 1,
+				GcKind::Compacting => totals.compacting_collections += 1,
+				GcKind::Shrinking => totals.shrinking_collections += 1
+			}
+			totals.total_pause_ns += stats.pause_ns;
+			if stats.pause_ns > totals.max_pause_ns {
+				totals.max_pause_ns = stats.pause_ns;
+			}
+			totals.bytes_copied += stats.bytes_copied;
+		}
+		
+		for &(_, ref listener) in &*self.listeners.borrow() {
+			listener.after_gc(&stats);
+		}
+	}
+	
+	/// Updates the state of a mutator that refers into the heap after a
+	/// collection, before the strategy is done with it.
+	fn update_after_gc(&self, heap: &Copying) {
+		self.buffer.set(AllocBuffer::empty());
+		
+		if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+			profiler.update(|ptr| unsafe { heap.forwarded(ptr) });
+		}
+		
+		let allocated = self.allocated.get();
+		self.allocated_at_gc.set(allocated);
+		
+		if let Some(budget) = self.allocation_budget {
+			self.next_budget_gc.set(allocated + budget);
+			self.update_alloc_limit();
+		}
+	}
+	
+	fn collect_walkers(&self, stats: &mut GcCollectionStats, mutators: &[&GcHeap], stack: Option<(ptr_t, ptr_t)>) {
 		let mut walkers : Vec<Box<RootWalker>> = Vec::new();
+		let mut borrows = Vec::new();
+		let mut stacks = stack.into_iter().collect::<Vec<_>>();
+		
+		for mutator in mutators {
+			// The unused part of the buffers is marked, so the strategy can find
+			// the objects a stack points into.
+			
+			let mut buffer = mutator.buffer.get();
+			unsafe { buffer.retire() };
+			mutator.buffer.set(buffer);
+			
+			if !ptr::eq(*mutator, self) {
+				stacks.extend(mutator.stack.get());
+			}
+			
+			// Add the root handles walker if there are root handles.
+			
+			let mut handles = mutator.handles.data.borrow_mut();
+			if handles.ptrs.len() != handles.free.len() {
+				let ptr = (*handles.ptrs).as_mut_ptr();
+				let end = unsafe { ptr.offset(handles.ptrs.len() as isize) };
+				
+				walkers.push(Box::new(RootHandlesWalker {
+					ptr: ptr,
+					end: end
+				}));
+			}
+			
+			// Add the local scopes walker if there are any.
+			
+			let scopes = mutator.scopes.borrow();
+			if scopes.len() > 0 {
+				walkers.push(Box::new(LocalScopesWalker {
+					scopes: unsafe { transmute::<&[LocalScopeData], *const [LocalScopeData]>(&**scopes) },
+					scope: 0,
+					vec: 0,
+					index: 0
+				}));
+			}
+			
+			borrows.push((handles, scopes));
+		}
 		
-		// Add the root handles walker if there are root handles.
+		let mut census = if self.census_on_gc.get() { Some(GcCensus::new()) } else { None };
+		let mut tracer = self.tracer.borrow_mut();
 		
-		let mut handles = self.handles.data.borrow_mut();
-		if handles.ptrs.len() != handles.free.len() {
-			let ptr = (*handles.ptrs).as_mut_ptr();
-			let end = unsafe { ptr.offset(handles.ptrs.len() as isize) };
+		{
+			let mut cx = GcContext {
+				kind: stats.kind,
+				walker: self.walker(),
+				stats: stats,
+				census: census.as_mut(),
+				tracer: tracer.as_mut(),
+				stacks: &stacks
+			};
 			
-			walkers.push(Box::new(RootHandlesWalker {
-				ptr: ptr,
-				end: end
-			}));
+			self.with_strategy(|heap| heap.gc(walkers, &mut cx));
 		}
 		
-		// Add the local scopes walker if there are any.
+		if census.is_some() {
+			*self.last_census.borrow_mut() = census;
+		}
+	}
+	
+	/// Starts recording the phases of every collection. The events are
+	/// tagged with the provided process and thread id.
+	pub fn start_tracing(&self, pid: u32, tid: u32) {
+		*self.tracer.borrow_mut() = Some(GcTracer::new(pid, tid));
+	}
+	
+	pub fn stop_tracing(&self) -> Option<GcTracer> {
+		self.tracer.borrow_mut().take()
+	}
+	
+	/// Takes a census of the live heap by tracing all objects reachable from
+	/// the roots. This does not move any objects.
+	pub fn census(&self) -> GcCensus {
+		let mut census = GcCensus::new();
+		self.walk_graph(&mut census);
+		census
+	}
+	
+	/// When enabled, every collection records a census of the objects it
+	/// copies, which is available through `last_census`.
+	pub fn set_census_on_gc(&self, enabled: bool) {
+		self.census_on_gc.set(enabled);
 		
-		let scopes = self.scopes.borrow();
-		if scopes.len() > 0 {
-			walkers.push(Box::new(LocalScopesWalker {
-				scopes: unsafe { transmute::<&[LocalScopeData], *const [LocalScopeData]>(&**scopes) },
-				scope: 0,
-				vec: 0,
-				index: 0
-			}));
+		if !enabled {
+			*self.last_census.borrow_mut() = None;
 		}
+	}
+	
+	pub fn last_census(&self) -> Option<GcCensus> {
+		self.last_census.borrow().clone()
+	}
+	
+	/// Subscribes a listener to GC events. The returned id can be passed
+	/// to `remove_listener` to unsubscribe again. Listeners must not add or
+	/// remove listeners from within their callbacks.
+	///
+	/// Panics when the heap belongs to an `Isolate`; use
+	/// `Isolate::add_listener` instead.
+	pub fn add_listener(&self, listener: Box<GcListener>) -> usize {
+		if self.isolated.get() {
+			panic!("Listeners of an isolated heap must be added through Isolate::add_listener");
+		}
+		
+		self.push_listener(listener)
+	}
+	
+	fn push_listener(&self, listener: Box<GcListener>) -> usize {
+		let id = self.next_listener.get();
+		self.next_listener.set(id + 1);
+		
+		self.listeners.borrow_mut().push((id, listener));
+		
+		id
+	}
+	
+	pub fn remove_listener(&self, id: usize) -> bool {
+		let mut listeners = self.listeners.borrow_mut();
 		
-		self.heap.borrow_mut().gc(walkers, &*self.walker);
+		if let Some(index) = listeners.iter().position(|&(other, _)| other == id) {
+			listeners.remove(index);
+			true
+		} else {
+			false
+		}
 	}
 	
 	pub fn mem_allocated(&self) -> usize {
-		self.heap.borrow().mem_allocated()
+		self.with_strategy(|heap| heap.mem_allocated())
 	}
 	
 	pub fn mem_used(&self) -> usize {
-		self.heap.borrow().mem_used()
+		self.with_strategy(|heap| heap.mem_used())
+	}
+	
+	pub fn stats(&self) -> GcHeapStats {
+		let mut stats = *self.totals.borrow();
+		
+		stats.total_allocated = self.allocated.get();
+		stats.mem_allocated = self.mem_allocated();
+		stats.mem_used = self.mem_used();
+		
+		{
+			let handles = self.handles.data.borrow();
+			stats.root_handles = handles.ptrs.len() - handles.free.len();
+		}
+		
+		for scope in &*self.scopes.borrow() {
+			stats.local_handles += scope.len();
+		}
+		
+		self.with_strategy(|heap| heap.fill_stats(&mut stats));
+		
+		stats
+	}
+	
+	fn leaked_roots(&self) -> Vec<GcLeakedRoot> {
+		let handles = self.handles.data.borrow();
+		
+		handles.ptrs.iter().enumerate()
+			.filter(|&(_, ptr)| !ptr.is_null())
+			.map(|(index, &ptr)| GcLeakedRoot {
+				handle: index as u32,
+				object: unsafe { GcObject::from_ptr(ptr) }
+			})
+			.collect()
 	}
 	
 	pub fn new_local_scope(&self) -> LocalScope {
@@ -359,8 +1128,46 @@ impl GcHeap {
 	}
 }
 
+impl Drop for GcHeap {
+	fn drop(&mut self) {
+		// Report the roots that outlive the heap and invalidate them, so they
+		// cannot be used to access the memory that is about to be released.
+		
+		let leaked = self.leaked_roots();
+		
+		for &(_, ref listener) in &*self.listeners.borrow() {
+			listener.heap_dropped(&leaked);
+		}
+		
+		{
+			let mut handles = self.handles.data.borrow_mut();
+			
+			handles.dropped = true;
+			handles.ptrs = Vec::new();
+			handles.generations = Vec::new();
+			handles.free = Vec::new();
+		}
+		
+		if let GcBackend::Shared(ref shared) = self.backend {
+			// Mark the unused part of the buffer, so the other mutators can still
+			// walk the from space. A collection that starts after the safepoint
+			// waits until the mutator is unregistered.
+			
+			shared.safepoint(self);
+			
+			let mut buffer = self.buffer.get();
+			unsafe { buffer.retire() };
+			self.buffer.set(buffer);
+			
+			shared.unregister();
+		}
+	}
+}
+
 trait RootWalker {
 	unsafe fn next(&mut self) -> *mut ptr_t;
+	
+	fn name(&self) -> &'static str;
 }
 struct RootHandlesWalker {
 	ptr: *mut ptr_t,
@@ -368,6 +1175,10 @@ struct RootHandlesWalker {
 }
 
 impl RootWalker for RootHandlesWalker {
+	fn name(&self) -> &'static str {
+		"root handles"
+	}
+	
 	unsafe fn next(&mut self) -> *mut ptr_t {
 		while self.ptr < self.end {
 			let ptr = self.ptr;
@@ -393,39 +1204,166 @@ struct LocalScopesWalker {
 }
 
 impl RootWalker for LocalScopesWalker {
+	fn name(&self) -> &'static str {
+		"local scopes"
+	}
+	
 	unsafe fn next(&mut self) -> *mut ptr_t {
 		let scopes = transmute::<_, &[LocalScopeData]>(self.scopes);
 		
-		if self.scope == scopes.len() {
-			return ptr::null_mut();
+		while self.scope < scopes.len() {
+			let scope = &scopes[self.scope];
+			
+			let vec = if self.vec == 0 {
+				&scope.current
+			} else {
+				&scope.handles[self.vec - 1]
+			};
+			
+			if self.index < vec.len() {
+				let ptr = (*vec).as_ptr().offset(self.index as isize) as *mut ptr_t;
+				
+				self.index += 1;
+				
+				return ptr;
+			}
+			
+			// Move to the next vector, skipping empty ones, e.g. the current
+			// vector of a scope nothing was allocated in yet.
+			
+			self.vec += 1;
+			self.index = 0;
+			
+			if self.vec - 1 == scope.handles.len() {
+				self.vec = 0;
+				self.scope += 1;
+			}
+		}
+		
+		ptr::null_mut()
+	}
+}
+
+/// A slot of an object that references another object.
+struct Slot {
+	ptr: *mut usize,
+	tag: Option<GcTag>
+}
+
+impl Slot {
+	unsafe fn get(&self) -> ptr_t {
+		match self.tag {
+			Some(ref tag) => (tag.decode)(tag, *self.ptr),
+			None => *self.ptr as ptr_t
+		}
+	}
+	
+	unsafe fn set(&self, target: ptr_t) {
+		*self.ptr = match self.tag {
+			Some(ref tag) => (tag.encode)(tag, *self.ptr, target),
+			None => target as usize
+		};
+	}
+	
+	/// Returns the byte offset of the slot in the object at `ptr`.
+	fn offset_from(&self, ptr: ptr_t) -> usize {
+		self.ptr as usize - ptr as usize
+	}
+}
+
+unsafe fn walk_object<F: FnMut(Slot)>(ptr: ptr_t, walker: &GcWalker, mut f: F) {
+	let gc_header = GcMemHeader::from_ptr(ptr);
+	if !gc_header.has_pointers() {
+		return;
+	}
+	
+	let ty = gc_header.get_type_id();
+	let size = gc_header.get_size();
+	let ptrs = size / size_of::<usize>();
+	
+	if (gc_header.is_vector() || gc_header.is_hash_map()) && !gc_header.is_array() {
+		// A vector or hash map only references its storage.
+		
+		let storage = ptr as *mut usize;
+		if *storage != 0 {
+			f(Slot {
+				ptr: storage,
+				tag: None
+			});
 		}
 		
-		let scope = &scopes[self.scope];
+		return;
+	}
+	
+	// A type that holds a pointer is a whole number of words, so anything else,
+	// like items smaller than a word, cannot hold one.
+	
+	if ptrs == 0 || size % size_of::<usize>() != 0 {
+		return;
+	}
+	
+	if gc_header.is_array() {
+		// Only the initialized items of the storage of a vector are walked.
 		
-		let vec = if self.vec == 0 {
-			&scope.current
+		let count = if gc_header.is_vector() {
+			*transmute::<_, *const usize>(ptr).offset(1)
 		} else {
-			&scope.handles[self.vec - 1]
+			*transmute::<_, *const usize>(ptr)
 		};
 		
-		let ptr = (*vec).as_ptr().offset(self.index as isize) as *mut ptr_t;
-		
-		self.index += 1;
+		let mut child = ptr.offset(gc_header.items_offset() as isize);
+		let end = child.offset((count * size) as isize);
 		
-		if self.index == vec.len() {
-			self.vec += 1;
-			self.index = 0;
+		while child < end {
+			walk_block(child, ty, ptrs, walker, &mut f);
 			
-			if self.vec - 1 == scope.handles.len() {
-				self.vec = 0;
-				self.scope += 1;
-			}
+			child = child.offset(size as isize);
 		}
+	} else {
+		walk_block(ptr, ty, ptrs, walker, &mut f);
+	}
+}
+
+unsafe fn walk_block<F: FnMut(Slot)>(ptr: ptr_t, ty: u32, ptrs: usize, walker: &GcWalker, f: &mut F) {
+	for i in 0..ptrs {
+		let offset = (ptr as *mut usize).offset(i as isize);
 		
-		ptr
+		match walker.walk(ty, ptr, i as u32) {
+			GcWalk::End => return,
+			GcWalk::Skip => {},
+			GcWalk::Pointer => {
+				if *offset != 0 {
+					f(Slot {
+						ptr: offset,
+						tag: None
+					});
+				}
+			}
+			GcWalk::Tagged(tag) => {
+				if *offset & tag.mask == tag.tag && !(tag.decode)(&tag, *offset).is_null() {
+					f(Slot {
+						ptr: offset,
+						tag: Some(tag)
+					});
+				}
+			}
+		}
 	}
 }
 
+/// Returns the number of bytes an object occupies including its header.
+unsafe fn object_size(ptr: ptr_t) -> usize {
+	let gc_header = GcMemHeader::from_ptr(ptr);
+	
+	let size = if gc_header.is_array() {
+		gc_header.items_offset() + gc_header.get_size() * *transmute::<_, *const usize>(ptr)
+	} else {
+		gc_header.get_size()
+	};
+	
+	size_of::<GcMemHeader>() + ((size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1))
+}
+
 pub trait GcWalker {
 	fn walk(&self, ty: u32, ptr: ptr_t, index: u32) -> GcWalk;
 }
@@ -434,5 +1372,44 @@ pub trait GcWalker {
 pub enum GcWalk {
 	Pointer,
 	Skip,
-	End
+	End,
+	/// The word holds a pointer when its tag bits match.
+	Tagged(GcTag)
+}
+
+/// Describes words that only hold a pointer when some of their bits have a
+/// specific value, e.g. NaN boxed values or tagged integers. The collector
+/// decodes the pointer, forwards it and encodes the new location back into
+/// the word.
+#[derive(Copy, Clone, Debug)]
+pub struct GcTag {
+	/// The bits of the word that hold the tag.
+	pub mask: usize,
+	/// The value of the tag bits when the word holds a pointer.
+	pub tag: usize,
+	/// Returns the pointer held by a word with a matching tag.
+	pub decode: fn(&GcTag, usize) -> ptr_t,
+	/// Returns the word with its pointer replaced by `ptr`.
+	pub encode: fn(&GcTag, usize, ptr_t) -> usize
+}
+
+impl GcTag {
+	/// Words that have `tag` in the `mask` bits hold the pointer in the other
+	/// bits.
+	pub fn new(mask: usize, tag: usize) -> GcTag {
+		GcTag {
+			mask: mask,
+			tag: tag,
+			decode: GcTag::decode_masked,
+			encode: GcTag::encode_masked
+		}
+	}
+	
+	fn decode_masked(&self, word: usize) -> ptr_t {
+		(word & !self.mask) as ptr_t
+	}
+	
+	fn encode_masked(&self, word: usize, ptr: ptr_t) -> usize {
+		(word & self.mask) | ptr as usize
+	}
 }
@@ -0,0 +1,226 @@
+use gc::HeapSizingPolicy;
+use gc::os::PAGE_SIZE;
+use std::env;
+use std::error::Error;
+use std::fmt;
+
+pub struct GcOpts {
+	pub initial_heap: usize,
+	pub slow_growth_factor: f64,
+	pub fast_growth_factor: f64,
+	/// The heap will not grow beyond this size, with the exception of the
+	/// room needed to copy all live objects.
+	pub max_heap: Option<usize>,
+	/// Collect after this many bytes were allocated since the last collection,
+	/// instead of only when the heap is exhausted.
+	pub allocation_budget: Option<usize>,
+	/// The policy that sizes the heap. When not provided, the heap grows using
+	/// the slow and fast growth factors. It must be `Send` because a shared
+	/// heap collects on whichever thread needs to.
+	pub sizing: Option<Box<HeapSizingPolicy + Send>>,
+	/// Also treat every word on the stack of the collecting thread, and of
+	/// the parked threads of a shared heap, that points into an object as a
+	/// reference to that object. These objects are pinned: they survive the
+	/// collection and are not moved.
+	pub conservative_stack: bool
+}
+
+impl GcOpts {
+	pub fn default() -> GcOpts {
+		GcOpts {
+			initial_heap: 16 * 1024 * 1024, // 16M
+			slow_growth_factor: 1.5f64,
+			fast_growth_factor: 3f64,
+			max_heap: None,
+			allocation_budget: None,
+			sizing: None,
+			conservative_stack: false
+		}
+	}
+	
+	pub fn builder() -> GcOptsBuilder {
+		GcOptsBuilder {
+			opts: GcOpts::default(),
+			error: None
+		}
+	}
+	
+	/// Returns the default options with the overrides from the `RJS_GC_*`
+	/// environment variables applied. See `GcOptsBuilder::env`.
+	pub fn from_env() -> Result<GcOpts, GcOptsError> {
+		GcOpts::builder().env().build()
+	}
+	
+	pub fn validate(&self) -> Result<(), GcOptsError> {
+		if self.initial_heap == 0 || self.initial_heap % PAGE_SIZE != 0 {
+			return Err(GcOptsError::InvalidInitialHeap(self.initial_heap));
+		}
+		if !(self.slow_growth_factor > 1f64) {
+			return Err(GcOptsError::InvalidSlowGrowthFactor(self.slow_growth_factor));
+		}
+		if !(self.fast_growth_factor > 1f64) {
+			return Err(GcOptsError::InvalidFastGrowthFactor(self.fast_growth_factor));
+		}
+		if let Some(max_heap) = self.max_heap {
+			if max_heap < self.initial_heap {
+				return Err(GcOptsError::InvalidMaxHeap(max_heap));
+			}
+		}
+		if self.allocation_budget == Some(0) {
+			return Err(GcOptsError::InvalidAllocationBudget(0));
+		}
+		
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GcOptsError {
+	/// The initial heap must be a non zero multiple of the page size.
+	InvalidInitialHeap(usize),
+	InvalidSlowGrowthFactor(f64),
+	InvalidFastGrowthFactor(f64),
+	/// The maximum heap must be at least the initial heap.
+	InvalidMaxHeap(usize),
+	InvalidAllocationBudget(usize),
+	/// An environment variable could not be parsed.
+	InvalidEnv {
+		name: String,
+		value: String
+	}
+}
+
+impl fmt::Display for GcOptsError {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			GcOptsError::InvalidInitialHeap(size) => write!(fmt, "initial_heap must be a non zero multiple of {} but is {}", PAGE_SIZE, size),
+			GcOptsError::InvalidSlowGrowthFactor(factor) => write!(fmt, "slow_growth_factor must be more than 1 but is {}", factor),
+			GcOptsError::InvalidFastGrowthFactor(factor) => write!(fmt, "fast_growth_factor must be more than 1 but is {}", factor),
+			GcOptsError::InvalidMaxHeap(size) => write!(fmt, "max_heap must not be less than initial_heap but is {}", size),
+			GcOptsError::InvalidAllocationBudget(size) => write!(fmt, "allocation_budget must be more than 0 but is {}", size),
+			GcOptsError::InvalidEnv { ref name, ref value } => write!(fmt, "invalid value {:?} for {}", value, name)
+		}
+	}
+}
+
+impl Error for GcOptsError {}
+
+pub struct GcOptsBuilder {
+	opts: GcOpts,
+	error: Option<GcOptsError>
+}
+
+impl GcOptsBuilder {
+	pub fn initial_heap(mut self, initial_heap: usize) -> GcOptsBuilder {
+		self.opts.initial_heap = initial_heap;
+		self
+	}
+	
+	pub fn slow_growth_factor(mut self, factor: f64) -> GcOptsBuilder {
+		self.opts.slow_growth_factor = factor;
+		self
+	}
+	
+	pub fn fast_growth_factor(mut self, factor: f64) -> GcOptsBuilder {
+		self.opts.fast_growth_factor = factor;
+		self
+	}
+	
+	pub fn max_heap(mut self, max_heap: usize) -> GcOptsBuilder {
+		self.opts.max_heap = Some(max_heap);
+		self
+	}
+	
+	pub fn allocation_budget(mut self, budget: usize) -> GcOptsBuilder {
+		self.opts.allocation_budget = Some(budget);
+		self
+	}
+	
+	pub fn sizing(mut self, sizing: Box<HeapSizingPolicy + Send>) -> GcOptsBuilder {
+		self.opts.sizing = Some(sizing);
+		self
+	}
+	
+	pub fn conservative_stack(mut self, enabled: bool) -> GcOptsBuilder {
+		self.opts.conservative_stack = enabled;
+		self
+	}
+	
+	/// Applies overrides from the environment. Sizes accept a K, M or G suffix.
+	///
+	/// * `RJS_GC_INITIAL_HEAP`
+	/// * `RJS_GC_SLOW_GROWTH_FACTOR`
+	/// * `RJS_GC_FAST_GROWTH_FACTOR`
+	/// * `RJS_GC_MAX_HEAP`
+	/// * `RJS_GC_ALLOCATION_BUDGET`
+	/// * `RJS_GC_CONSERVATIVE_STACK`, either 0 or 1
+	pub fn env(mut self) -> GcOptsBuilder {
+		if let Some(size) = self.env_var("RJS_GC_INITIAL_HEAP", parse_size) {
+			self.opts.initial_heap = size;
+		}
+		if let Some(factor) = self.env_var("RJS_GC_SLOW_GROWTH_FACTOR", |value| value.parse().ok()) {
+			self.opts.slow_growth_factor = factor;
+		}
+		if let Some(factor) = self.env_var("RJS_GC_FAST_GROWTH_FACTOR", |value| value.parse().ok()) {
+			self.opts.fast_growth_factor = factor;
+		}
+		if let Some(size) = self.env_var("RJS_GC_MAX_HEAP", parse_size) {
+			self.opts.max_heap = Some(size);
+		}
+		if let Some(size) = self.env_var("RJS_GC_ALLOCATION_BUDGET", parse_size) {
+			self.opts.allocation_budget = Some(size);
+		}
+		if let Some(enabled) = self.env_var("RJS_GC_CONSERVATIVE_STACK", parse_flag) {
+			self.opts.conservative_stack = enabled;
+		}
+		
+		self
+	}
+	
+	fn env_var<T, F: Fn(&str) -> Option<T>>(&mut self, name: &str, parse: F) -> Option<T> {
+		let value = match env::var(name) {
+			Ok(value) => value,
+			Err(_) => return None
+		};
+		
+		let result = parse(value.trim());
+		
+		if result.is_none() && self.error.is_none() {
+			self.error = Some(GcOptsError::InvalidEnv {
+				name: name.to_string(),
+				value: value
+			});
+		}
+		
+		result
+	}
+	
+	pub fn build(self) -> Result<GcOpts, GcOptsError> {
+		if let Some(error) = self.error {
+			return Err(error);
+		}
+		
+		self.opts.validate()?;
+		
+		Ok(self.opts)
+	}
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+	match value {
+		"0" => Some(false),
+		"1" => Some(true),
+		_ => None
+	}
+}
+
+fn parse_size(value: &str) -> Option<usize> {
+	let (value, multiplier) = match value.chars().last() {
+		Some('k') | Some('K') => (&value[..value.len() - 1], 1024),
+		Some('m') | Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+		Some('g') | Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+		_ => (value, 1)
+	};
+	
+	value.parse::<usize>().ok().and_then(|value| value.checked_mul(multiplier))
+}
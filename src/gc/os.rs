@@ -7,6 +7,8 @@ use std::ptr;
 use std::mem;
 use gc::ptr_t;
 
+pub const PAGE_SIZE : usize = 4 * 1024;
+
 #[cfg(target_os = "windows")]
 unsafe fn map(addr: ptr_t, size: usize) -> ptr_t {
 	assert!(size != 0);
@@ -65,6 +67,13 @@ unsafe fn unmap(addr: ptr_t, _: usize) {
 	}
 }
 
+#[cfg(target_os = "windows")]
+unsafe fn decommit(addr: ptr_t, size: usize) {
+	if VirtualFree(mem::transmute(addr), size as size_t, MEM_DECOMMIT) == 0 {
+		panic!("Error in VirtualFree");
+	}
+}
+
 /* LINUX VERSION
 static void
 pages_unmap(void *addr, size_t size)
@@ -84,6 +93,54 @@ pages_unmap(void *addr, size_t size)
 }
 */
 
+#[cfg(target_os = "windows")]
+extern "system" {
+	fn GetCurrentThreadStackLimits(low_limit: *mut usize, high_limit: *mut usize);
+}
+
+/// Returns the base of the stack of the current thread. Stacks grow down, so
+/// this is the end of the stack.
+#[cfg(target_os = "windows")]
+pub fn stack_base() -> ptr_t {
+	let mut low = 0;
+	let mut high = 0;
+	
+	unsafe { GetCurrentThreadStackLimits(&mut low, &mut high) };
+	
+	high as ptr_t
+}
+
+/// The number of registers stored by `spill_registers`.
+pub const SPILLED_REGISTERS : usize = 8;
+
+/// Stores the callee saved registers, so a scan of the stack that covers
+/// `registers` also sees the values the callers only keep in a register.
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+pub fn spill_registers(registers: &mut [usize; SPILLED_REGISTERS]) {
+	unsafe {
+		::std::arch::asm!(
+			"mov [{0}], rbx",
+			"mov [{0} + 8], rbp",
+			"mov [{0} + 16], rdi",
+			"mov [{0} + 24], rsi",
+			"mov [{0} + 32], r12",
+			"mov [{0} + 40], r13",
+			"mov [{0} + 48], r14",
+			"mov [{0} + 56], r15",
+			in(reg) registers.as_mut_ptr(),
+			options(nostack, preserves_flags)
+		);
+	}
+}
+
+/// Registers are not spilled on this architecture, so pointers that are only
+/// held in a register are not found by a scan of the stack.
+#[cfg(not(target_arch = "x86_64"))]
+#[inline(never)]
+pub fn spill_registers(_registers: &mut [usize; SPILLED_REGISTERS]) {
+}
+
 pub struct Memory {
 	ptr: ptr_t,
 	size: usize
@@ -116,6 +173,18 @@ impl Memory {
 	pub fn size(&self) -> usize {
 		self.size
 	}
+	
+	/// Gives the pages after the first `size` bytes back to the system.
+	pub fn shrink(&mut self, size: usize) {
+		assert!(size <= self.size && size % PAGE_SIZE == 0);
+		
+		if size == 0 {
+			*self = Memory::empty();
+		} else if size < self.size {
+			unsafe { decommit(self.ptr.offset(size as isize), self.size - size) };
+			self.size = size;
+		}
+	}
 }
 
 impl Drop for Memory {
@@ -125,3 +194,40 @@ impl Drop for Memory {
 		}
 	}
 }
+
+#[cfg(not(target_os = "windows"))]
+unsafe fn map(_: ptr_t, size: usize) -> ptr_t {
+	let ret = mmap(ptr::null_mut(), size as size_t, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANON, -1, 0);
+	if ret == MAP_FAILED { ptr::null() } else { ret as ptr_t }
+}
+
+#[cfg(not(target_os = "windows"))]
+unsafe fn unmap(addr: ptr_t, size: usize) {
+	munmap(addr as *mut c_void, size as size_t);
+}
+
+#[cfg(not(target_os = "windows"))]
+extern "C" {
+	fn pthread_self() -> usize;
+	fn pthread_getattr_np(thread: usize, attr: *mut u64) -> c_int;
+	fn pthread_attr_getstack(attr: *const u64, addr: *mut *mut c_void, size: *mut size_t) -> c_int;
+	fn pthread_attr_destroy(attr: *mut u64) -> c_int;
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn stack_base() -> ptr_t {
+	unsafe {
+		let mut attr = [0u64; 16];
+		let mut addr = ptr::null_mut();
+		let mut size = 0;
+		assert!(pthread_getattr_np(pthread_self(), attr.as_mut_ptr()) == 0);
+		pthread_attr_getstack(attr.as_ptr(), &mut addr, &mut size);
+		pthread_attr_destroy(attr.as_mut_ptr());
+		(addr as usize + size as usize) as ptr_t
+	}
+}
+
+#[cfg(not(target_os = "windows"))]
+unsafe fn decommit(addr: ptr_t, size: usize) {
+	munmap(addr as *mut c_void, size as size_t);
+}
@@ -0,0 +1,157 @@
+// Sampling allocation profiler. Every `interval` allocated bytes, the
+// allocation that crosses the interval is recorded together with the current
+// allocation tag or a backtrace. Collections update the samples so the report
+// shows which allocation sites retain memory.
+
+use gc::ptr_t;
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GcProfilerOpts {
+	/// Average number of allocated bytes between two samples.
+	pub interval: usize,
+	/// Capture a backtrace for samples taken without an allocation tag.
+	pub backtraces: bool
+}
+
+impl GcProfilerOpts {
+	pub fn default() -> GcProfilerOpts {
+		GcProfilerOpts {
+			interval: 512 * 1024, // 512K
+			backtraces: false
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct GcSample {
+	pub ty: u32,
+	pub size: usize,
+	pub site: String,
+	/// Number of collections the sampled object survived.
+	pub collections: usize,
+	ptr: ptr_t
+}
+
+impl GcSample {
+	/// Whether the object survived the last collection that ran while the
+	/// profiler was active.
+	pub fn is_alive(&self) -> bool {
+		!self.ptr.is_null()
+	}
+}
+
+struct SiteReport<'a> {
+	site: &'a str,
+	samples: usize,
+	bytes: usize,
+	live_samples: usize,
+	live_bytes: usize
+}
+
+pub struct GcProfiler {
+	opts: GcProfilerOpts,
+	samples: Vec<GcSample>,
+	tag: Option<String>
+}
+
+impl GcProfiler {
+	pub fn new(opts: GcProfilerOpts) -> GcProfiler {
+		if opts.interval == 0 {
+			panic!("interval must be more than 0");
+		}
+		
+		GcProfiler {
+			opts: opts,
+			samples: Vec::new(),
+			tag: None
+		}
+	}
+	
+	pub fn interval(&self) -> usize {
+		self.opts.interval
+	}
+	
+	pub fn samples(&self) -> &[GcSample] {
+		&self.samples
+	}
+	
+	pub fn set_tag(&mut self, tag: Option<String>) {
+		self.tag = tag;
+	}
+	
+	pub fn sample(&mut self, ptr: ptr_t, ty: u32, size: usize) {
+		let site = if let Some(ref tag) = self.tag {
+			tag.clone()
+		} else if self.opts.backtraces {
+			Backtrace::force_capture().to_string()
+		} else {
+			"(unknown)".to_string()
+		};
+		
+		self.samples.push(GcSample {
+			ty: ty,
+			size: size,
+			site: site,
+			collections: 0,
+			ptr: ptr
+		});
+	}
+	
+	/// Updates the samples after a collection. `forwarded` returns the new
+	/// location of an object, or null when it did not survive.
+	pub fn update<F: Fn(ptr_t) -> ptr_t>(&mut self, forwarded: F) {
+		for sample in &mut self.samples {
+			if !sample.ptr.is_null() {
+				sample.ptr = forwarded(sample.ptr);
+				
+				if !sample.ptr.is_null() {
+					sample.collections += 1;
+				}
+			}
+		}
+	}
+	
+	/// Writes the samples aggregated per allocation site. Every sample is
+	/// counted as `interval` bytes, which makes the byte counts an estimate
+	/// of the real allocation volume.
+	pub fn write_report(&self, writer: &mut Write) -> io::Result<()> {
+		let mut sites : HashMap<&str, SiteReport> = HashMap::new();
+		
+		for sample in &self.samples {
+			let report = sites.entry(&sample.site).or_insert_with(|| SiteReport {
+				site: &sample.site,
+				samples: 0,
+				bytes: 0,
+				live_samples: 0,
+				live_bytes: 0
+			});
+			
+			report.samples += 1;
+			report.bytes += self.opts.interval;
+			
+			if sample.is_alive() {
+				report.live_samples += 1;
+				report.live_bytes += self.opts.interval;
+			}
+		}
+		
+		let mut sites = sites.into_iter().map(|(_, report)| report).collect::<Vec<_>>();
+		sites.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.site.cmp(b.site)));
+		
+		writeln!(writer, "{:>12} {:>8} {:>12} {:>8}  site", "bytes", "samples", "live bytes", "live")?;
+		
+		for report in &sites {
+			writeln!(
+				writer,
+				"{:>12} {:>8} {:>12} {:>8}  {}",
+				report.bytes, report.samples, report.live_bytes, report.live_samples, report.site
+			)?;
+		}
+		
+		Ok(())
+	}
+}
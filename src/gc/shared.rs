@@ -0,0 +1,222 @@
+// A heap that is shared between threads. Every thread allocates through its
+// own mutator, which is a `GcHeap` with its own root handles, local scopes and
+// allocation buffer. Collections stop all mutators at a safepoint.
+
+use gc::{GcHeap, GcWalker, GcOpts};
+use gc::strategy::copying::Copying;
+use std::sync::{Arc, Mutex, MutexGuard, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct SharedHeap {
+	pub heap: Mutex<Copying>,
+	pub walker: Box<GcWalker + Send + Sync>,
+	pub allocation_budget: Option<usize>,
+	pub conservative_stack: bool,
+	state: Mutex<SafepointState>,
+	parked: Condvar,
+	resumed: Condvar,
+	requested: AtomicBool
+}
+
+// The strategy is only accessed while holding its lock, and the mutators in
+// the safepoint state are only accessed while they are parked. The sizing
+// policy the strategy owns is `Send`.
+unsafe impl Send for SharedHeap {}
+unsafe impl Sync for SharedHeap {}
+
+struct SafepointState {
+	mutators: usize,
+	/// Mutators that are parked at a safepoint or in a safe region. Their roots
+	/// may be accessed by the thread that stopped the world.
+	parked: Vec<*const GcHeap>,
+	/// The mutator that stopped the world.
+	stopped: Option<*const GcHeap>
+}
+
+impl SafepointState {
+	/// Returns whether another mutator than `mutator` stopped the world. The
+	/// mutator that stopped it keeps running, and may reach a safepoint from
+	/// a listener or while collecting.
+	fn stopped_by_other(&self, mutator: &GcHeap) -> bool {
+		match self.stopped {
+			Some(stopper) => stopper != mutator as *const GcHeap,
+			None => false
+		}
+	}
+}
+
+impl SharedHeap {
+	/// Returns whether a thread is waiting for the other mutators to reach a
+	/// safepoint.
+	#[inline(always)]
+	pub fn is_requested(&self) -> bool {
+		self.requested.load(Ordering::Acquire)
+	}
+	
+	pub fn register(&self) {
+		let mut state = self.state.lock().unwrap();
+		
+		while state.stopped.is_some() {
+			state = self.resumed.wait(state).unwrap();
+		}
+		
+		state.mutators += 1;
+	}
+	
+	pub fn unregister(&self) {
+		let mut state = self.state.lock().unwrap();
+		
+		state.mutators -= 1;
+		
+		self.parked.notify_all();
+	}
+	
+	pub fn safepoint(&self, mutator: &GcHeap) {
+		let state = self.state.lock().unwrap();
+		
+		if state.stopped_by_other(mutator) {
+			drop(self.park(mutator, state));
+		}
+	}
+	
+	fn park<'a>(&'a self, mutator: &GcHeap, state: MutexGuard<'a, SafepointState>) -> MutexGuard<'a, SafepointState> {
+		mutator.with_stack(|stack| {
+			let mut state = state;
+			
+			mutator.stack.set(stack);
+			state.parked.push(mutator as *const GcHeap);
+			self.parked.notify_all();
+			
+			while state.stopped.is_some() {
+				state = self.resumed.wait(state).unwrap();
+			}
+			
+			remove_parked(&mut state, mutator);
+			mutator.stack.set(None);
+			
+			state
+		})
+	}
+	
+	pub fn enter_safe_region(&self, mutator: &GcHeap) {
+		let mut state = self.state.lock().unwrap();
+		
+		state.parked.push(mutator as *const GcHeap);
+		self.parked.notify_all();
+	}
+	
+	pub fn leave_safe_region(&self, mutator: &GcHeap) {
+		let mut state = self.state.lock().unwrap();
+		
+		while state.stopped_by_other(mutator) {
+			state = self.resumed.wait(state).unwrap();
+		}
+		
+		remove_parked(&mut state, mutator);
+	}
+	
+	/// Waits until all other mutators are parked and calls `f` with all
+	/// mutators, starting with the calling one. When another thread is stopping
+	/// the world, the calling mutator parks until that thread is done first.
+	/// `f` is called without holding any lock, so it may allocate or stop the
+	/// world again.
+	pub fn stop_the_world<R, F: FnOnce(&[&GcHeap]) -> R>(&self, mutator: &GcHeap, f: F) -> R {
+		let mut state = self.state.lock().unwrap();
+		
+		if state.stopped == Some(mutator as *const GcHeap) {
+			let mutators = parked_mutators(&state, mutator);
+			drop(state);
+			
+			return f(&mutators);
+		}
+		
+		while state.stopped.is_some() {
+			state = self.park(mutator, state);
+		}
+		
+		state.stopped = Some(mutator as *const GcHeap);
+		self.requested.store(true, Ordering::Release);
+		
+		while state.parked.len() + 1 < state.mutators {
+			state = self.parked.wait(state).unwrap();
+		}
+		
+		// The parked mutators stay parked until the world is resumed.
+		
+		let mutators = parked_mutators(&state, mutator);
+		drop(state);
+		
+		let result = f(&mutators);
+		
+		let mut state = self.state.lock().unwrap();
+		
+		state.stopped = None;
+		self.requested.store(false, Ordering::Release);
+		self.resumed.notify_all();
+		
+		result
+	}
+}
+
+fn parked_mutators<'a>(state: &SafepointState, mutator: &'a GcHeap) -> Vec<&'a GcHeap> {
+	let mut mutators = vec![mutator];
+	mutators.extend(state.parked.iter().map(|&parked| unsafe { &*parked }));
+	mutators
+}
+
+fn remove_parked(state: &mut SafepointState, mutator: &GcHeap) {
+	let index = state.parked.iter().position(|&parked| parked == mutator as *const GcHeap).unwrap();
+	state.parked.swap_remove(index);
+}
+
+/// A heap that multiple threads can allocate from. Every thread that uses the
+/// heap must register a mutator through `mutator`, which is only usable from
+/// that thread.
+///
+/// Collections stop the world: the thread that collects waits until every
+/// other mutator has reached a safepoint. Mutators reach a safepoint whenever
+/// they allocate or call `GcHeap::safepoint`. Threads that block or run for a
+/// long time without touching the heap must do so from within
+/// `GcHeap::safe_region`, or collections on other threads will wait for them.
+///
+/// Statistics, listeners, census, profiling and tracing are per mutator, and
+/// are only reported to the mutator that performed the collection.
+#[derive(Clone)]
+pub struct SharedGcHeap {
+	inner: Arc<SharedHeap>
+}
+
+impl SharedGcHeap {
+	/// Creates a new shared heap.
+	pub fn new(walker: Box<GcWalker + Send + Sync>, opts: GcOpts) -> SharedGcHeap {
+		if let Err(error) = opts.validate() {
+			panic!("{}", error);
+		}
+		
+		let allocation_budget = opts.allocation_budget;
+		let conservative_stack = opts.conservative_stack;
+		
+		SharedGcHeap {
+			inner: Arc::new(SharedHeap {
+				heap: Mutex::new(Copying::new(opts)),
+				walker: walker,
+				allocation_budget: allocation_budget,
+				conservative_stack: conservative_stack,
+				state: Mutex::new(SafepointState {
+					mutators: 0,
+					parked: Vec::new(),
+					stopped: None
+				}),
+				parked: Condvar::new(),
+				resumed: Condvar::new(),
+				requested: AtomicBool::new(false)
+			})
+		}
+	}
+	
+	/// Registers a mutator for the calling thread. The mutator is unregistered
+	/// when it is dropped. It must not be moved while local scopes are open.
+	pub fn mutator(&self) -> GcHeap {
+		GcHeap::from_shared(self.inner.clone())
+	}
+}
@@ -0,0 +1,157 @@
+// Heap sizing policies decide how large the space is that a collection
+// copies into, which becomes the space new objects are allocated from.
+
+use gc::GcKind;
+
+/// The state of the heap passed to a `HeapSizingPolicy`.
+#[derive(Copy, Clone, Debug)]
+pub struct HeapSizingInfo {
+	/// The kind of the collection the policy is consulted for.
+	pub kind: GcKind,
+	pub initial_heap: usize,
+	/// Size of the space objects are allocated from.
+	pub space_size: usize,
+	/// Bytes in use in that space. Before a collection this includes garbage;
+	/// after a collection these are the bytes that survived.
+	pub used: usize,
+	/// Size of the allocation that triggered the collection, if any.
+	pub failed: usize,
+	/// Fraction of the allocated bytes that survived the previous collection,
+	/// or zero when there has not been a collection yet.
+	pub last_used: f64
+}
+
+pub trait HeapSizingPolicy {
+	/// Returns the size of the space to copy the live objects into. The size
+	/// is rounded up to whole pages, and is never made smaller than the bytes
+	/// currently in use since all of them could survive. Shrinking collections
+	/// always use that minimum.
+	fn before_gc(&mut self, info: &HeapSizingInfo) -> usize;
+	
+	fn after_gc(&mut self, _info: &HeapSizingInfo) {}
+}
+
+/// The default policy. The heap grows by the slow growth factor, or by the
+/// fast growth factor when the fill factor of the previous collection exceeded
+/// the threshold. The heap never shrinks.
+pub struct GrowingHeapSizing {
+	pub slow_growth_factor: f64,
+	pub fast_growth_factor: f64,
+	pub fast_growth_threshold: f64,
+	last_size: usize,
+	current_size: usize
+}
+
+impl GrowingHeapSizing {
+	pub fn new(slow_growth_factor: f64, fast_growth_factor: f64) -> GrowingHeapSizing {
+		GrowingHeapSizing {
+			slow_growth_factor: slow_growth_factor,
+			fast_growth_factor: fast_growth_factor,
+			fast_growth_threshold: 0.8,
+			last_size: 0,
+			current_size: 0
+		}
+	}
+}
+
+impl HeapSizingPolicy for GrowingHeapSizing {
+	fn before_gc(&mut self, info: &HeapSizingInfo) -> usize {
+		// We use the fill factor of the previous run as a basis and ensure that
+		// we have at least enough room to accept the allocation that failed last
+		// (were we not able to reclaim any memory).
+		//
+		// The room we allocate comes down to the current allocated memory times the
+		// fill factor times the growth factor.
+		
+		let growth_factor = if info.last_used > self.fast_growth_threshold {
+			self.fast_growth_factor
+		} else {
+			self.slow_growth_factor
+		};
+		
+		let mut target_size = info.used + info.failed;
+		
+		if info.last_used > 0f64 {
+			target_size = (target_size as f64 * info.last_used) as usize
+		}
+		
+		if target_size < info.initial_heap {
+			target_size = info.initial_heap;
+		}
+		
+		target_size = (target_size as f64 * growth_factor) as usize;
+		
+		if target_size < self.last_size {
+			target_size = self.last_size;
+		}
+		
+		self.current_size = info.space_size;
+		
+		target_size
+	}
+	
+	fn after_gc(&mut self, info: &HeapSizingInfo) {
+		self.last_size = if info.kind == GcKind::Shrinking {
+			info.space_size
+		} else {
+			self.current_size
+		};
+	}
+}
+
+/// Keeps the heap at its initial size. Allocations fail when the live
+/// objects do not leave enough room.
+pub struct FixedHeapSizing;
+
+impl HeapSizingPolicy for FixedHeapSizing {
+	fn before_gc(&mut self, info: &HeapSizingInfo) -> usize {
+		info.initial_heap
+	}
+}
+
+/// Sizes the heap so that the live objects occupy the target fraction of it,
+/// growing and shrinking as needed within the provided bounds.
+pub struct OccupancyHeapSizing {
+	pub target_occupancy: f64,
+	pub min_heap: usize,
+	pub max_heap: usize
+}
+
+impl OccupancyHeapSizing {
+	pub fn new(target_occupancy: f64, min_heap: usize, max_heap: usize) -> OccupancyHeapSizing {
+		if target_occupancy <= 0f64 || target_occupancy > 1f64 {
+			panic!("target_occupancy must be more than 0 and at most 1");
+		}
+		if min_heap > max_heap {
+			panic!("min_heap must not be more than max_heap");
+		}
+		
+		OccupancyHeapSizing {
+			target_occupancy: target_occupancy,
+			min_heap: min_heap,
+			max_heap: max_heap
+		}
+	}
+}
+
+impl HeapSizingPolicy for OccupancyHeapSizing {
+	fn before_gc(&mut self, info: &HeapSizingInfo) -> usize {
+		// Estimate the live size using the fill factor of the previous run.
+		
+		let live = if info.last_used > 0f64 {
+			(info.used as f64 * info.last_used) as usize
+		} else {
+			info.used
+		};
+		
+		let target_size = ((live + info.failed) as f64 / self.target_occupancy) as usize;
+		
+		if target_size < self.min_heap {
+			self.min_heap
+		} else if target_size > self.max_heap {
+			self.max_heap
+		} else {
+			target_size
+		}
+	}
+}
@@ -0,0 +1,227 @@
+// Writes the live heap in the .heapsnapshot format used by the memory panel
+// of the Chrome DevTools.
+
+use gc::{GcHeap, GcRoot, GcObject, GcGraphVisitor, ptr_t};
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+const NODE_FIELD_COUNT : usize = 6;
+
+const NODE_TYPE_ARRAY : usize = 1;
+const NODE_TYPE_OBJECT : usize = 3;
+const NODE_TYPE_SYNTHETIC : usize = 9;
+
+const EDGE_TYPE_ELEMENT : usize = 1;
+const EDGE_TYPE_PROPERTY : usize = 2;
+
+// The synthetic nodes are the first three nodes of the snapshot.
+const NODE_HANDLES : usize = 1;
+const NODE_LOCALS : usize = 2;
+const SYNTHETIC_NODES : usize = 3;
+
+/// Provides human readable names for the types and fields in a heap snapshot.
+pub trait GcSnapshotNames {
+	fn type_name(&self, ty: u32) -> String {
+		format!("Type {}", ty)
+	}
+	
+	/// Returns the name of the pointer field at byte offset `offset` of an
+	/// object of type `ty`.
+	fn edge_name(&self, _ty: u32, _is_array: bool, offset: usize) -> String {
+		format!("@{}", offset)
+	}
+}
+
+struct DefaultNames;
+
+impl GcSnapshotNames for DefaultNames {}
+
+struct Edge {
+	name: usize,
+	to: ptr_t
+}
+
+struct SnapshotBuilder<'a> {
+	names: &'a GcSnapshotNames,
+	strings: Vec<String>,
+	string_index: HashMap<String, usize>,
+	objects: Vec<GcObject>,
+	// Node index of every object, including the synthetic nodes.
+	index: HashMap<ptr_t, usize>,
+	handle_edges: Vec<Edge>,
+	local_edges: Vec<Edge>,
+	edges: Vec<Vec<Edge>>
+}
+
+impl<'a> SnapshotBuilder<'a> {
+	fn string(&mut self, string: String) -> usize {
+		if let Some(&index) = self.string_index.get(&string) {
+			return index;
+		}
+		
+		let index = self.strings.len();
+		self.strings.push(string.clone());
+		self.string_index.insert(string, index);
+		
+		index
+	}
+	
+	fn node_offset(&self, ptr: ptr_t) -> usize {
+		self.index[&ptr] * NODE_FIELD_COUNT
+	}
+	
+	fn write(&mut self, writer: &mut Write) -> io::Result<()> {
+		let root_name = self.string("".to_string());
+		let handles_name = self.string("(Root handles)".to_string());
+		let locals_name = self.string("(Local scopes)".to_string());
+		let handles_edge = self.string("handles".to_string());
+		let locals_edge = self.string("locals".to_string());
+		
+		let mut type_names = Vec::with_capacity(self.objects.len());
+		for i in 0..self.objects.len() {
+			let name = self.names.type_name(self.objects[i].ty);
+			type_names.push(self.string(name));
+		}
+		
+		let node_count = SYNTHETIC_NODES + self.objects.len();
+		let edge_count = 2 + self.handle_edges.len() + self.local_edges.len() +
+			self.edges.iter().fold(0, |count, edges| count + edges.len());
+		
+		write!(writer, "{{\"snapshot\":{{\"meta\":{{")?;
+		write!(writer, "\"node_fields\":[\"type\",\"name\",\"id\",\"self_size\",\"edge_count\",\"trace_node_id\"],")?;
+		write!(writer, "\"node_types\":[[\"hidden\",\"array\",\"string\",\"object\",\"code\",\"closure\",\"regexp\",\"number\",\"native\",\"synthetic\",\"concatenated string\",\"sliced string\"],\"string\",\"number\",\"number\",\"number\",\"number\"],")?;
+		write!(writer, "\"edge_fields\":[\"type\",\"name_or_index\",\"to_node\"],")?;
+		write!(writer, "\"edge_types\":[[\"context\",\"element\",\"property\",\"internal\",\"hidden\",\"shortcut\",\"weak\"],\"string_or_number\",\"node\"],")?;
+		write!(writer, "\"trace_function_info_fields\":[],\"trace_node_fields\":[],\"sample_fields\":[],\"location_fields\":[]}},")?;
+		write!(writer, "\"node_count\":{},\"edge_count\":{},\"trace_function_count\":0}},\n", node_count, edge_count)?;
+		
+		// Nodes. Node ids must be unique; we simply number them.
+		
+		write!(writer, "\"nodes\":[")?;
+		write!(writer, "{},{},{},0,2,0", NODE_TYPE_SYNTHETIC, root_name, 1)?;
+		write!(writer, ",\n{},{},{},0,{},0", NODE_TYPE_SYNTHETIC, handles_name, 3, self.handle_edges.len())?;
+		write!(writer, ",\n{},{},{},0,{},0", NODE_TYPE_SYNTHETIC, locals_name, 5, self.local_edges.len())?;
+		
+		for (i, object) in self.objects.iter().enumerate() {
+			write!(
+				writer,
+				",\n{},{},{},{},{},0",
+				if object.is_array { NODE_TYPE_ARRAY } else { NODE_TYPE_OBJECT },
+				type_names[i],
+				(SYNTHETIC_NODES + i) * 2 + 1,
+				object.size,
+				self.edges[i].len()
+			)?;
+		}
+		
+		// Edges, grouped by the node they originate from.
+		
+		write!(writer, "],\n\"edges\":[")?;
+		write!(writer, "{},{},{}", EDGE_TYPE_PROPERTY, handles_edge, NODE_HANDLES * NODE_FIELD_COUNT)?;
+		write!(writer, ",\n{},{},{}", EDGE_TYPE_PROPERTY, locals_edge, NODE_LOCALS * NODE_FIELD_COUNT)?;
+		
+		for edge in self.handle_edges.iter().chain(self.local_edges.iter()) {
+			write!(writer, ",\n{},{},{}", EDGE_TYPE_ELEMENT, edge.name, self.node_offset(edge.to))?;
+		}
+		
+		for edges in &self.edges {
+			for edge in edges {
+				write!(writer, ",\n{},{},{}", EDGE_TYPE_PROPERTY, edge.name, self.node_offset(edge.to))?;
+			}
+		}
+		
+		write!(writer, "],\n\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[],\"locations\":[],\n\"strings\":[")?;
+		
+		for (i, string) in self.strings.iter().enumerate() {
+			if i > 0 {
+				write!(writer, ",\n")?;
+			}
+			write_json_string(writer, string)?;
+		}
+		
+		write!(writer, "]}}\n")
+	}
+}
+
+impl<'a> GcGraphVisitor for SnapshotBuilder<'a> {
+	fn root(&mut self, root: GcRoot, target: ptr_t) {
+		match root {
+			GcRoot::Handle(index) => {
+				self.handle_edges.push(Edge {
+					name: index as usize,
+					to: target
+				});
+			}
+			GcRoot::Local { .. } => {
+				let index = self.local_edges.len();
+				self.local_edges.push(Edge {
+					name: index,
+					to: target
+				});
+			}
+		}
+	}
+	
+	fn object(&mut self, object: &GcObject) {
+		self.index.insert(object.ptr, SYNTHETIC_NODES + self.objects.len());
+		self.objects.push(*object);
+		self.edges.push(Vec::new());
+	}
+	
+	fn edge(&mut self, _from: ptr_t, offset: usize, to: ptr_t) {
+		// Edges are always reported directly after the object they belong to.
+		
+		let object = self.objects[self.objects.len() - 1];
+		let name = self.names.edge_name(object.ty, object.is_array, offset);
+		let name = self.string(name);
+		
+		let last = self.edges.len() - 1;
+		self.edges[last].push(Edge {
+			name: name,
+			to: to
+		});
+	}
+}
+
+fn write_json_string(writer: &mut Write, string: &str) -> io::Result<()> {
+	write!(writer, "\"")?;
+	
+	for c in string.chars() {
+		match c {
+			'"' => write!(writer, "\\\"")?,
+			'\\' => write!(writer, "\\\\")?,
+			'\n' => write!(writer, "\\n")?,
+			'\r' => write!(writer, "\\r")?,
+			'\t' => write!(writer, "\\t")?,
+			c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+			c => write!(writer, "{}", c)?
+		}
+	}
+	
+	write!(writer, "\"")
+}
+
+impl GcHeap {
+	/// Writes a snapshot of the live heap in the Chrome DevTools .heapsnapshot
+	/// format. When no names are provided, types and fields are named after
+	/// their type id and byte offset.
+	pub fn write_heap_snapshot(&self, writer: &mut Write, names: Option<&GcSnapshotNames>) -> io::Result<()> {
+		let default_names = DefaultNames;
+		
+		let mut builder = SnapshotBuilder {
+			names: names.unwrap_or(&default_names),
+			strings: Vec::new(),
+			string_index: HashMap::new(),
+			objects: Vec::new(),
+			index: HashMap::new(),
+			handle_edges: Vec::new(),
+			local_edges: Vec::new(),
+			edges: Vec::new()
+		};
+		
+		self.walk_graph(&mut builder);
+		
+		builder.write(writer)
+	}
+}
@@ -1,19 +1,24 @@
 extern crate libc;
-extern crate time;
 
-use gc::strategy::Strategy;
-use gc::os::Memory;
-use gc::{RootWalker, GcOpts, GcMemHeader, GcWalker, GcWalk, ptr_t};
+use gc::strategy::{Strategy, GcContext};
+use gc::os::{Memory, PAGE_SIZE};
+use gc::{RootWalker, GcOpts, GcMemHeader, GcHeapStats, GcKind, ptr_t};
+use gc::{HeapSizingPolicy, HeapSizingInfo, GrowingHeapSizing};
+use gc::{walk_object, object_size};
 use std::ptr;
-use std::mem::{size_of, transmute, swap};
-
-const PAGE_SIZE : usize = 4 * 1024;
+use std::mem::{size_of, transmute, swap, replace};
+use std::collections::HashMap;
 
 struct Header {
 	forward: ptr_t,
 	size: usize
 }
 
+/// Marks the first word of an unused part of the from space, e.g. the rest of
+/// an allocation buffer. The other bits hold the size of the unused part. The
+/// forward pointer of an object never has this bit set.
+const FILLER : usize = 1;
+
 impl Header {
 	fn new(size: usize) -> Header {
 		Header {
@@ -56,140 +61,445 @@ impl Block {
 		
 		memory.offset(size_of::<Header>() as isize)
 	}
+	
+	fn contains(&self, ptr: ptr_t) -> bool {
+		let start = unsafe { self.memory.ptr() } as usize;
+		let ptr = ptr as usize;
+		
+		ptr >= start && ptr < start + self.offset
+	}
+	
+	/// Reserves up to `size` bytes, but at least `min_size`, without writing
+	/// any headers.
+	unsafe fn alloc_chunk(&mut self, size: usize, min_size: usize) -> (ptr_t, usize) {
+		let available = self.memory.size() - self.offset;
+		if available < min_size {
+			return (ptr::null(), 0);
+		}
+		
+		let size = if available < size { available } else { size };
+		let memory = self.memory.ptr().offset(self.offset as isize);
+		
+		self.offset += size;
+		
+		(memory, size)
+	}
+}
+
+/// The number of bytes a mutator reserves from the heap at a time when it
+/// allocates through an `AllocBuffer`.
+pub const ALLOC_BUFFER_SIZE : usize = 32 * 1024;
+
+/// A chunk of the from space that is owned by a single mutator, so objects
+/// can be allocated from it without synchronization. The chunk is zeroed when
+/// it is handed out. Buffers become invalid on every collection.
+#[derive(Copy, Clone)]
+pub struct AllocBuffer {
+	ptr: ptr_t,
+	end: ptr_t
+}
+
+impl AllocBuffer {
+	pub fn empty() -> AllocBuffer {
+		AllocBuffer {
+			ptr: ptr::null(),
+			end: ptr::null()
+		}
+	}
+	
+	/// Allocates in the same layout as `Strategy::alloc_raw`. Returns null when
+	/// the buffer is exhausted.
+	#[inline(always)]
+	pub unsafe fn alloc(&mut self, size: usize) -> ptr_t {
+		let size = ((size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1)) + size_of::<Header>();
+		
+		if (self.end as usize) - (self.ptr as usize) < size {
+			return ptr::null();
+		}
+		
+		let memory = self.ptr;
+		
+		(*(memory as *mut Header)) = Header::new(size);
+		
+		self.ptr = memory.offset(size as isize);
+		
+		memory.offset(size_of::<Header>() as isize)
+	}
+	
+	/// Marks the unused part of the buffer as a filler, so the from space can
+	/// be walked object by object, and empties the buffer.
+	pub unsafe fn retire(&mut self) {
+		let size = (self.end as usize) - (self.ptr as usize);
+		
+		if size > 0 {
+			*(self.ptr as *mut usize) = size | FILLER;
+		}
+		
+		*self = AllocBuffer::empty();
+	}
+}
+
+/// The memory of a from space that contained pinned objects. It is kept until
+/// a collection no longer pins any object in it.
+struct PinnedBlock {
+	memory: Memory,
+	objects: Vec<ptr_t>
+}
+
+impl PinnedBlock {
+	fn contains(&self, ptr: ptr_t) -> bool {
+		let start = unsafe { self.memory.ptr() } as usize;
+		let ptr = ptr as usize;
+		
+		ptr >= start && ptr < start + self.memory.size()
+	}
 }
 
 pub struct Copying {
-	opts: GcOpts,
+	initial_heap: usize,
+	max_heap: Option<usize>,
+	sizing: Box<HeapSizingPolicy + Send>,
 	from: Block,
 	to: Memory,
-	last_size: usize,
 	last_used: f64,
-	last_failed: usize
+	last_failed: usize,
+	release_to: bool,
+	pinned: Vec<PinnedBlock>,
+	/// The size of the objects in `pinned`.
+	pinned_bytes: usize,
+	/// Objects pinned by the last collection, whose forward pointers are reset
+	/// in `after_gc`.
+	unpin: Vec<ptr_t>,
+	/// Pinned blocks that are released in `after_gc`.
+	release_pinned: Vec<Memory>,
+	/// The identity hashes handed out so far, by object. Objects that move
+	/// take their hash along.
+	hashes: HashMap<ptr_t, u32>,
+	next_hash: u32
 }
 
 impl Copying {
 	pub fn new(opts: GcOpts) -> Copying {
 		let memory = Memory::alloc(opts.initial_heap).unwrap();
 		
+		let sizing = match opts.sizing {
+			Some(sizing) => sizing,
+			None => Box::new(GrowingHeapSizing::new(opts.slow_growth_factor, opts.fast_growth_factor))
+		};
+		
 		Copying {
-			opts: opts,
+			initial_heap: opts.initial_heap,
+			max_heap: opts.max_heap,
+			sizing: sizing,
 			from: Block {
 				memory: memory,
 				offset: 0
 			},
 			to: Memory::empty(),
-			last_size: 0,
 			last_used: 0f64,
-			last_failed: 0
+			last_failed: 0,
+			release_to: false,
+			pinned: Vec::new(),
+			pinned_bytes: 0,
+			unpin: Vec::new(),
+			release_pinned: Vec::new(),
+			hashes: HashMap::new(),
+			next_hash: 0
 		}
 	}
 	
-	unsafe fn copy(&mut self, mut walkers: Vec<Box<RootWalker>>, walker: &GcWalker) {
-		let allocated = self.from.offset;
-		
-		// Calculate the new size of the heap. We use the fill factor of the previous
-		// run as a basis and ensure that we have at least enough room to accept the
-		// allocation that failed last (were we not able to reclaim any memory).
-		//
-		// The room we allocate comes down to the current allocated memory times the
-		// fill factor times the growth factor. The growth factor is taken from
-		// the configuration. 
-		
-		let growth_factor = if self.last_used > 0.8 {
-			self.opts.fast_growth_factor
-		} else {
-			self.opts.slow_growth_factor
-		};
+	unsafe fn copy(&mut self, mut walkers: Vec<Box<RootWalker>>, cx: &mut GcContext) {
+		// Objects in pinned blocks may be copied into the to space too.
+		
+		let allocated = self.from.offset + self.pinned_bytes;
+		
+		// Calculate the new size of the heap. The sizing policy decides, but the
+		// to space must always be able to hold everything that is allocated now.
 		
-		let mut target_size = self.from.offset + self.last_failed;
+		let mut target_size = self.sizing.before_gc(&self.sizing_info(cx.kind));
 		self.last_failed = 0;
 		
-		if self.last_used > 0f64 {
-			target_size = (target_size as f64 * self.last_used) as usize
-		}
+		// A shrinking collection copies into the smallest space that is
+		// guaranteed to fit. The pages the survivors do not need and the other
+		// space are released afterwards.
 		
-		if target_size < self.opts.initial_heap {
-			target_size = self.opts.initial_heap;
+		if let Some(max_heap) = self.max_heap {
+			if target_size > max_heap {
+				target_size = max_heap;
+			}
 		}
 		
-		target_size = (target_size as f64 * growth_factor) as usize;
-		target_size = (target_size + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
-		
-		if target_size < self.last_size {
-			target_size = self.last_size;
+		if target_size < allocated || cx.kind == GcKind::Shrinking {
+			target_size = allocated;
 		}
 		
-		self.last_failed = 0;
+		target_size = (target_size + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
 		
-		// Ensure that the target heap is large enough.
+		// Ensure that the target heap has the requested size.
 		
-		if self.to.size() < target_size {
+		if self.to.size() != target_size {
+			cx.begin("remap");
+			
 			// First set to empty to first release our allocated memory.
 			self.to = Memory::empty();
 			self.to = Memory::alloc(target_size).unwrap();
+			
+			cx.end();
 		}
 		
 		let mut forwarder = Forwarder {
-			target: self.to.ptr()
+			target: self.to.ptr(),
+			objects: 0
+		};
+		
+		// Pin the objects referenced from the stacks by forwarding them to
+		// themselves, before any root can copy them.
+		
+		let pinned = if cx.stacks.is_empty() {
+			Vec::new()
+		} else {
+			cx.begin("stacks");
+			
+			let pinned = self.find_pinned(cx.stacks);
+			
+			for &ptr in &pinned {
+				Header::from_ptr(ptr).forward = Header::offset_from_user(ptr);
+			}
+			
+			for &ptr in &pinned {
+				self.scan_object(ptr, &mut forwarder, cx);
+			}
+			
+			cx.end();
+			
+			pinned
 		};
 		
 		// Walk all GC roots.
 		
 		for walker in &mut walkers {
+			cx.begin(walker.name());
+			
 			loop {
 				let ptr = walker.next();
 				if ptr.is_null() {
 					break;
 				}
 				
+				if cfg!(debug_assertions) && !self.contains(*ptr) {
+					panic!("The {} reference {:?}, which does not belong to this heap", walker.name(), *ptr);
+				}
+				
 				*ptr = forwarder.forward(*ptr);
 			}
+			
+			cx.end();
 		}
 		
 		// Walk the to space.
 		
+		cx.begin("scan");
+		
 		let mut ptr = Header::offset_to_user(self.to.ptr());
 		
 		while ptr < forwarder.target {
-			let header = Header::from_ptr(ptr);
-			let gc_header = GcMemHeader::from_ptr(ptr);
-			let ty = gc_header.get_type_id();
-			let size = gc_header.get_size();
-			let ptrs = size / size_of::<usize>();
+			self.scan_object(ptr, &mut forwarder, cx);
 			
-			if gc_header.is_array() {
-				let count = *transmute::<_, *const usize>(ptr);
-
-				let mut child = ptr.offset(size_of::<usize>() as isize);
-				let end = child.offset((count * size) as isize);
-
-				while child < end {
-					process_block(child, ty, ptrs, &mut forwarder, walker);
-					
-					child = child.offset(size as isize);
+			ptr = ptr.offset(Header::from_ptr(ptr).size as isize);
+		}
+		
+		cx.end();
+		
+		// Move the identity hashes along with their objects, and forget those
+		// of objects that did not survive.
+		
+		if !self.hashes.is_empty() {
+			cx.begin("hashes");
+			
+			for (ptr, hash) in replace(&mut self.hashes, HashMap::new()) {
+				let forward = Header::from_ptr(ptr).forward;
+				if !forward.is_null() {
+					self.hashes.insert(Header::offset_to_user(forward), hash);
 				}
-				
-			} else {
-				process_block(ptr, ty, ptrs, &mut forwarder, walker);
 			}
 			
-			ptr = ptr.offset(header.size as isize);
+			cx.end();
 		}
 		
+		cx.stats.bytes_copied = forwarder.target as usize - self.to.ptr() as usize;
+		cx.stats.objects_copied = forwarder.objects;
+		
 		// Swap the from and to space.
 		
+		cx.begin("swap");
+		
 		self.from.offset = forwarder.target as usize - self.to.ptr() as usize;
 		swap(&mut self.from.memory, &mut self.to);
 		
+		// Keep the memory of pinned objects. The old from space can then not
+		// be reused as the next to space.
+		
+		let mut blocks = Vec::new();
+		
+		for block in self.pinned.drain(..) {
+			let objects = pinned.iter().cloned().filter(|&ptr| block.contains(ptr)).collect::<Vec<_>>();
+			
+			if objects.is_empty() {
+				self.release_pinned.push(block.memory);
+			} else {
+				blocks.push(PinnedBlock {
+					memory: block.memory,
+					objects: objects
+				});
+			}
+		}
+		
+		let to = &self.to;
+		let objects = pinned.iter().cloned().filter(|&ptr| {
+			let start = to.ptr() as usize;
+			ptr as usize >= start && (ptr as usize) < start + to.size()
+		}).collect::<Vec<_>>();
+		
+		if !objects.is_empty() {
+			blocks.push(PinnedBlock {
+				memory: replace(&mut self.to, Memory::empty()),
+				objects: objects
+			});
+		}
+		
+		if cx.kind == GcKind::Shrinking {
+			let used = (self.from.offset + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
+			self.from.memory.shrink(used);
+		}
+		
+		self.pinned = blocks;
+		self.pinned_bytes = pinned.iter().fold(0, |size, &ptr| size + Header::from_ptr(ptr).size);
+		self.unpin = pinned;
+		
+		cx.end();
+		
 		// Calculate the current fill rate.
 		
-		self.last_size = self.to.size();
 		self.last_used = self.from.offset as f64 / allocated as f64;
+		
+		let info = self.sizing_info(cx.kind);
+		self.sizing.after_gc(&info);
+		
+		self.release_to = cx.kind == GcKind::Shrinking;
+	}
+	
+	/// Walks the children of an object that stays in, or was copied into, the
+	/// to space.
+	unsafe fn scan_object(&self, ptr: ptr_t, forwarder: &mut Forwarder, cx: &mut GcContext) {
+		if let Some(ref mut census) = cx.census {
+			let gc_header = GcMemHeader::from_ptr(ptr);
+			census.add(gc_header.get_type_id(), object_size(ptr), gc_header.is_array());
+		}
+		
+		walk_object(ptr, cx.walker, |child| {
+			// Catch pointers to other heaps stored into objects of this heap.
+			
+			let target = child.get();
+			
+			if cfg!(debug_assertions) && !self.contains(target) {
+				panic!(
+					"Object {:?} of type {} references {:?} at offset {}, which does not belong to this heap",
+					ptr,
+					GcMemHeader::from_ptr(ptr).get_type_id(),
+					target,
+					child.offset_from(ptr)
+				);
+			}
+			
+			child.set(forwarder.forward(target));
+		});
+	}
+	
+	/// Returns the objects of the from space and the pinned blocks that any
+	/// word in the stacks points into, in address order.
+	unsafe fn find_pinned(&self, stacks: &[(ptr_t, ptr_t)]) -> Vec<ptr_t> {
+		// Collect the start and end of every object. The allocation buffers are
+		// retired before a collection, so the from space can be walked.
+		
+		let mut objects = Vec::new();
+		
+		let mut ptr = self.from.memory.ptr() as usize;
+		let end = ptr + self.from.offset;
+		
+		while ptr < end {
+			let word = *(ptr as *const usize);
+			
+			let size = if word & FILLER != 0 {
+				word & !FILLER
+			} else {
+				let size = (*(ptr as *const Header)).size;
+				objects.push((ptr, ptr + size));
+				size
+			};
+			
+			// Memory that was never allocated from is zeroed, which would stop
+			// the walk from advancing.
+			
+			assert!(size != 0, "unretired allocation buffer at {:#x} in the from space", ptr);
+			
+			ptr += size;
+		}
+		
+		for block in &self.pinned {
+			for &object in &block.objects {
+				let ptr = Header::offset_from_user(object) as usize;
+				objects.push((ptr, ptr + Header::from_ptr(object).size));
+			}
+		}
+		
+		objects.sort();
+		
+		let mut pinned = Vec::new();
+		
+		for &(start, end) in stacks {
+			let mut slot = (start as usize + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+			
+			while slot + size_of::<usize>() <= end as usize {
+				let word = ptr::read_volatile(slot as *const usize);
+				
+				let index = match objects.binary_search_by(|&(start, _)| start.cmp(&word)) {
+					Ok(index) => Some(index),
+					Err(0) => None,
+					Err(index) => Some(index - 1)
+				};
+				
+				if let Some(index) = index {
+					let (start, end) = objects[index];
+					if word < end {
+						pinned.push(Header::offset_to_user(start as ptr_t));
+					}
+				}
+				
+				slot += size_of::<usize>();
+			}
+		}
+		
+		pinned.sort();
+		pinned.dedup();
+		
+		pinned
+	}
+	
+	fn sizing_info(&self, kind: GcKind) -> HeapSizingInfo {
+		HeapSizingInfo {
+			kind: kind,
+			initial_heap: self.initial_heap,
+			space_size: self.from.memory.size(),
+			used: self.from.offset,
+			failed: self.last_failed,
+			last_used: self.last_used
+		}
 	}
 }
 
 struct Forwarder {
-	target: ptr_t
+	target: ptr_t,
+	objects: usize
 }
 
 impl Forwarder {
@@ -208,29 +518,13 @@ impl Forwarder {
 			);
 			
 			self.target = self.target.offset(header.size as isize);
+			self.objects += 1;
 		}
 		
 		Header::offset_to_user(header.forward)
 	}
 }
 
-unsafe fn process_block(ptr: ptr_t, ty: u32, ptrs: usize, forwarder: &mut Forwarder, walker: &GcWalker) {
-	for i in 0..ptrs {
-		match walker.walk(ty, ptr, i as u32) {
-			GcWalk::End => return,
-			GcWalk::Skip => {},
-			GcWalk::Pointer => {
-				let offset = (ptr as *mut ptr_t).offset(i as isize);
-				let child = *offset;
-				
-				if !child.is_null() {
-					*offset = forwarder.forward(child);
-				}
-			}
-		}
-	}
-}
-
 impl Strategy for Copying {
 	unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t {
 		// Round the size to the next pointer.
@@ -247,23 +541,107 @@ impl Strategy for Copying {
 		result
 	}
 	
+	unsafe fn refill(&mut self, buffer: &mut AllocBuffer, size: usize) -> bool {
+		let size = (size + (size_of::<usize>() - 1)) & !(size_of::<usize>() - 1);
+		
+		buffer.retire();
+		
+		let (ptr, chunk_size) = self.from.alloc_chunk(ALLOC_BUFFER_SIZE, size + size_of::<Header>());
+		
+		if ptr.is_null() {
+			self.last_failed = size;
+			return false;
+		}
+		
+		ptr::write_bytes(ptr as *mut u8, 0, chunk_size);
+		
+		buffer.ptr = ptr;
+		buffer.end = ptr.offset(chunk_size as isize);
+		
+		true
+	}
+	
 	fn mem_allocated(&self) -> usize {
-		self.from.memory.size() + self.to.size()
+		self.pinned.iter().fold(self.from.memory.size() + self.to.size(), |size, block| size + block.memory.size())
 	}
 	
 	fn mem_used(&self) -> usize {
-		self.from.offset
+		self.from.offset + self.pinned_bytes
+	}
+	
+	fn fill_stats(&self, stats: &mut GcHeapStats) {
+		stats.last_used = self.last_used;
+		stats.last_failed = self.last_failed;
+	}
+	
+	fn contains(&self, ptr: ptr_t) -> bool {
+		self.from.contains(ptr) || self.pinned.iter().any(|block| block.contains(ptr))
+	}
+	
+	unsafe fn forwarded(&self, ptr: ptr_t) -> ptr_t {
+		// The old from space is still mapped as the to space, so the forward
+		// pointers set by the last copy can still be read.
+		
+		let header = Header::from_ptr(ptr);
+		
+		if header.forward.is_null() {
+			ptr::null()
+		} else {
+			Header::offset_to_user(header.forward)
+		}
 	}
 	
-	fn gc(&mut self, walkers: Vec<Box<RootWalker>>, walker: &GcWalker) {
-		let start = time::precise_time_ns();
+	fn identity_hash(&mut self, ptr: ptr_t) -> u32 {
+		if let Some(hash) = self.identity_hash_if_assigned(ptr) {
+			return hash;
+		}
+		
+		// Spread consecutive hashes over all bits, skipping 0.
+		
+		loop {
+			self.next_hash = self.next_hash.wrapping_add(0x9e3779b9);
+			if self.next_hash != 0 {
+				break;
+			}
+		}
+		
+		self.hashes.insert(ptr, self.next_hash);
 		
+		self.next_hash
+	}
+	
+	fn identity_hash_if_assigned(&self, ptr: ptr_t) -> Option<u32> {
+		self.hashes.get(&ptr).cloned()
+	}
+	
+	fn collection_kind(&self, requested: GcKind) -> GcKind {
+		// There are no generations, so every collection is a full one. Since all
+		// live objects are copied, every collection also compacts.
+		
+		match requested {
+			GcKind::Minor | GcKind::Major => GcKind::Major,
+			GcKind::Compacting => GcKind::Compacting,
+			GcKind::Shrinking => GcKind::Shrinking
+		}
+	}
+	
+	fn gc(&mut self, walkers: Vec<Box<RootWalker>>, cx: &mut GcContext) {
 		unsafe {
-			self.copy(walkers, walker);
+			self.copy(walkers, cx);
+		}
+	}
+	
+	fn after_gc(&mut self) {
+		for &ptr in &self.unpin {
+			unsafe { Header::from_ptr(ptr).forward = ptr::null() };
 		}
 		
-		let elapsed = (time::precise_time_ns() - start) / 1_000_000;
-
-		println!("=== GC === allocated {} used {} ms {}", self.mem_allocated(), self.mem_used(), elapsed);
+		self.unpin.clear();
+		self.release_pinned.clear();
+		
+		if self.release_to {
+			self.to = Memory::empty();
+			self.release_to = false;
+		}
 	}
 }
@@ -2,14 +2,71 @@ pub mod copying;
 
 extern crate libc;
 
-use gc::{RootWalker, GcWalker, ptr_t};
+use gc::{RootWalker, GcWalker, GcKind, GcCollectionStats, GcHeapStats, GcCensus, GcTracer, ptr_t};
+use self::copying::AllocBuffer;
+
+/// Everything a strategy needs to perform a single collection.
+pub struct GcContext<'a> {
+	/// The kind of collection, as returned from `Strategy::collection_kind`.
+	pub kind: GcKind,
+	pub walker: &'a GcWalker,
+	pub stats: &'a mut GcCollectionStats,
+	pub census: Option<&'a mut GcCensus>,
+	pub tracer: Option<&'a mut GcTracer>,
+	/// Stack ranges to scan conservatively. The objects they point into must
+	/// survive the collection without being moved.
+	pub stacks: &'a [(ptr_t, ptr_t)]
+}
+
+impl<'a> GcContext<'a> {
+	pub fn begin(&mut self, name: &'static str) {
+		if let Some(ref mut tracer) = self.tracer {
+			tracer.begin(name);
+		}
+	}
+	
+	pub fn end(&mut self) {
+		if let Some(ref mut tracer) = self.tracer {
+			tracer.end();
+		}
+	}
+}
 
 pub trait Strategy {
 	unsafe fn alloc_raw(&mut self, size: usize) -> ptr_t;
 	
+	/// Replaces the buffer with a new zeroed chunk that can hold at least an
+	/// object of `size` bytes. Returns false when the heap is exhausted.
+	/// Buffers must be retired before every collection.
+	unsafe fn refill(&mut self, buffer: &mut AllocBuffer, size: usize) -> bool;
+	
 	fn mem_allocated(&self) -> usize;
 	
 	fn mem_used(&self) -> usize;
 	
-	fn gc(&mut self, walkers: Vec<Box<RootWalker>>, walker: &GcWalker);
+	fn fill_stats(&self, stats: &mut GcHeapStats);
+	
+	/// Returns whether the pointer references an object of this heap.
+	fn contains(&self, ptr: ptr_t) -> bool;
+	
+	/// Returns the new location of an object after a collection, or null when
+	/// the object did not survive. This is only valid for objects that were
+	/// allocated before the last collection, and only until `after_gc` is called.
+	unsafe fn forwarded(&self, ptr: ptr_t) -> ptr_t;
+	
+	/// Returns the identity hash of an object, assigning one on first use. The
+	/// hash stays the same when the object is moved.
+	fn identity_hash(&mut self, ptr: ptr_t) -> u32;
+	
+	/// Returns the identity hash of an object, or `None` when it has none yet.
+	fn identity_hash_if_assigned(&self, ptr: ptr_t) -> Option<u32>;
+	
+	/// Maps a requested kind of collection to the closest kind this strategy
+	/// supports.
+	fn collection_kind(&self, requested: GcKind) -> GcKind;
+	
+	fn gc(&mut self, walkers: Vec<Box<RootWalker>>, cx: &mut GcContext);
+	
+	/// Called when the heap is done with the results of a collection.
+	fn after_gc(&mut self);
 }
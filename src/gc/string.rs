@@ -0,0 +1,192 @@
+// Strings are arrays of code units that never contain pointers, marked with
+// `HEADER_STRING`. The item size in the header selects the representation:
+// one byte per code unit for Latin-1 strings, and two for UTF-16 strings.
+
+use gc::{GcHeap, GcMemHeader, Ptr, Root, Local, HEADER_PLAIN, HEADER_STRING, ptr_t};
+use std::char;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::{size_of, transmute};
+use std::slice;
+
+/// An immutable string in the heap, referenced through `Ptr<GcString>`,
+/// `Root<GcString>` or `Local<GcString>`. Strings are allocated with a type
+/// id of the caller's choosing, under which they show up in a census. The
+/// walker is never called for them.
+///
+/// Strings that only contain characters up to U+00FF are stored as Latin-1,
+/// all others as UTF-16. Equality and hashing are based on the UTF-16 code
+/// units, so they do not depend on the representation.
+pub struct GcString {
+	len: usize
+}
+
+/// The code units of a string.
+pub enum GcStringUnits<'a> {
+	Latin1(&'a [u8]),
+	Utf16(&'a [u16])
+}
+
+impl GcString {
+	/// Returns the number of UTF-16 code units.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+	
+	pub fn is_latin1(&self) -> bool {
+		let header = unsafe { GcMemHeader::from_ptr(self.ptr()) };
+		debug_assert!(header.is_string(), "Object {:?} is not a string", self.ptr());
+		
+		header.get_size() == 1
+	}
+	
+	pub fn units<'a>(&'a self) -> GcStringUnits<'a> {
+		unsafe {
+			let ptr = self.ptr().offset(size_of::<usize>() as isize);
+			
+			if self.is_latin1() {
+				GcStringUnits::Latin1(slice::from_raw_parts(ptr, self.len))
+			} else {
+				GcStringUnits::Utf16(slice::from_raw_parts(transmute(ptr), self.len))
+			}
+		}
+	}
+	
+	/// Returns the UTF-16 code unit at `index`.
+	pub fn unit(&self, index: usize) -> u16 {
+		match self.units() {
+			GcStringUnits::Latin1(units) => units[index] as u16,
+			GcStringUnits::Utf16(units) => units[index]
+		}
+	}
+	
+	fn ptr(&self) -> ptr_t {
+		self as *const GcString as ptr_t
+	}
+	
+	fn iter_units<'a>(&'a self) -> Box<Iterator<Item = u16> + 'a> {
+		match self.units() {
+			GcStringUnits::Latin1(units) => Box::new(units.iter().map(|&unit| unit as u16)),
+			GcStringUnits::Utf16(units) => Box::new(units.iter().cloned())
+		}
+	}
+}
+
+impl PartialEq for GcString {
+	fn eq(&self, other: &GcString) -> bool {
+		if self.len != other.len {
+			return false;
+		}
+		
+		match (self.units(), other.units()) {
+			(GcStringUnits::Latin1(a), GcStringUnits::Latin1(b)) => a == b,
+			(GcStringUnits::Utf16(a), GcStringUnits::Utf16(b)) => a == b,
+			_ => self.iter_units().eq(other.iter_units())
+		}
+	}
+}
+
+impl Eq for GcString {}
+
+impl PartialEq<str> for GcString {
+	fn eq(&self, other: &str) -> bool {
+		self.iter_units().eq(other.encode_utf16())
+	}
+}
+
+impl Hash for GcString {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		state.write_usize(self.len);
+		
+		for unit in self.iter_units() {
+			state.write_u16(unit);
+		}
+	}
+}
+
+/// Converts the string to UTF-8. Unpaired surrogates are replaced by U+FFFD.
+impl fmt::Display for GcString {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		match self.units() {
+			GcStringUnits::Latin1(units) => {
+				for &unit in units {
+					write!(fmt, "{}", unit as char)?;
+				}
+				
+				Ok(())
+			}
+			GcStringUnits::Utf16(units) => {
+				for c in char::decode_utf16(units.iter().cloned()) {
+					write!(fmt, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+				}
+				
+				Ok(())
+			}
+		}
+	}
+}
+
+impl fmt::Debug for GcString {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmt, "{:?}", self.to_string())
+	}
+}
+
+impl GcHeap {
+	/// Allocates a string. The walker is never called for type id `ty`.
+	pub unsafe fn alloc_string(&self, ty: u32, value: &str) -> Ptr<GcString> {
+		if value.chars().all(|c| (c as u32) <= 0xff) {
+			let mut string = self.alloc_array_with::<u8>(ty, 1, value.chars().count(), HEADER_PLAIN | HEADER_STRING);
+			
+			for (item, c) in string.iter_mut().zip(value.chars()) {
+				*item = c as u8;
+			}
+			
+			Ptr::from_ptr(string.ptr())
+		} else {
+			let units = value.encode_utf16().collect::<Vec<_>>();
+			
+			self.alloc_string_utf16(ty, &units)
+		}
+	}
+	
+	pub fn alloc_string_root(&self, ty: u32, value: &str) -> Root<GcString> {
+		unsafe { Root::new(self, self.alloc_string(ty, value)) }
+	}
+	
+	pub fn alloc_string_local(&self, ty: u32, value: &str) -> Local<GcString> {
+		self.alloc_local_from_ptr(unsafe { self.alloc_string(ty, value) })
+	}
+	
+	/// Allocates a string from UTF-16 code units, which may contain unpaired
+	/// surrogates.
+	pub unsafe fn alloc_string_utf16(&self, ty: u32, units: &[u16]) -> Ptr<GcString> {
+		if units.iter().all(|&unit| unit <= 0xff) {
+			let mut string = self.alloc_array_with::<u8>(ty, 1, units.len(), HEADER_PLAIN | HEADER_STRING);
+			
+			for (item, &unit) in string.iter_mut().zip(units) {
+				*item = unit as u8;
+			}
+			
+			Ptr::from_ptr(string.ptr())
+		} else {
+			let mut string = self.alloc_array_with::<u16>(ty, 2, units.len(), HEADER_PLAIN | HEADER_STRING);
+			
+			string.copy_from_slice(units);
+			
+			Ptr::from_ptr(string.ptr())
+		}
+	}
+	
+	pub fn alloc_string_utf16_root(&self, ty: u32, units: &[u16]) -> Root<GcString> {
+		unsafe { Root::new(self, self.alloc_string_utf16(ty, units)) }
+	}
+	
+	pub fn alloc_string_utf16_local(&self, ty: u32, units: &[u16]) -> Local<GcString> {
+		self.alloc_local_from_ptr(unsafe { self.alloc_string_utf16(ty, units) })
+	}
+}
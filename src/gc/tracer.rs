@@ -0,0 +1,96 @@
+// Records the phases of every collection in the Chrome trace event format,
+// so pauses can be inspected in chrome://tracing or Perfetto. Timestamps are
+// taken from `time::precise_time_ns`, so the events line up with other
+// spans recorded using the same clock.
+
+extern crate time;
+
+use std::io;
+use std::io::Write;
+
+struct TraceEvent {
+	name: &'static str,
+	start_ns: u64,
+	duration_ns: u64,
+	args: Option<String>
+}
+
+pub struct GcTracer {
+	pid: u32,
+	tid: u32,
+	events: Vec<TraceEvent>,
+	open: Vec<usize>
+}
+
+impl GcTracer {
+	pub fn new(pid: u32, tid: u32) -> GcTracer {
+		GcTracer {
+			pid: pid,
+			tid: tid,
+			events: Vec::new(),
+			open: Vec::new()
+		}
+	}
+	
+	/// Starts a phase. Phases nest and must be ended in reverse order.
+	pub fn begin(&mut self, name: &'static str) {
+		self.open.push(self.events.len());
+		self.events.push(TraceEvent {
+			name: name,
+			start_ns: time::precise_time_ns(),
+			duration_ns: 0,
+			args: None
+		});
+	}
+	
+	pub fn end(&mut self) {
+		let index = self.open.pop().expect("no open phase");
+		let event = &mut self.events[index];
+		
+		event.duration_ns = time::precise_time_ns() - event.start_ns;
+	}
+	
+	/// Attaches arguments to the innermost open phase. `args` must be the
+	/// contents of a JSON object.
+	pub fn set_args(&mut self, args: String) {
+		let index = *self.open.last().expect("no open phase");
+		self.events[index].args = Some(args);
+	}
+	
+	/// Writes the recorded events as a comma separated list of trace events,
+	/// to be merged into an existing trace.
+	pub fn write_events(&self, writer: &mut Write) -> io::Result<()> {
+		for (i, event) in self.events.iter().enumerate() {
+			if i > 0 {
+				write!(writer, ",\n")?;
+			}
+			
+			write!(
+				writer,
+				"{{\"name\":\"{}\",\"cat\":\"gc\",\"ph\":\"X\",\"ts\":{}.{:03},\"dur\":{}.{:03},\"pid\":{},\"tid\":{}",
+				event.name,
+				event.start_ns / 1000,
+				event.start_ns % 1000,
+				event.duration_ns / 1000,
+				event.duration_ns % 1000,
+				self.pid,
+				self.tid
+			)?;
+			
+			if let Some(ref args) = event.args {
+				write!(writer, ",\"args\":{{{}}}", args)?;
+			}
+			
+			write!(writer, "}}")?;
+		}
+		
+		Ok(())
+	}
+	
+	/// Writes the recorded events as a complete trace file.
+	pub fn write_json(&self, writer: &mut Write) -> io::Result<()> {
+		write!(writer, "{{\"traceEvents\":[\n")?;
+		self.write_events(writer)?;
+		write!(writer, "\n],\"displayTimeUnit\":\"ms\"}}\n")
+	}
+}
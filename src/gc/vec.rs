@@ -0,0 +1,174 @@
+// A vector is a small object that references its storage. The storage is an
+// array that also records how many of its items are initialized, so only
+// those are walked. When the storage is full, it is replaced by a larger one.
+
+use gc::{GcHeap, GcMemHeader, Ptr, Root, Local, HEADER_ARRAY, HEADER_VECTOR, ptr_t};
+use std::ops::{Deref, DerefMut};
+use std::marker::PhantomData;
+use std::mem::{size_of, transmute};
+use std::ptr;
+use std::slice;
+
+/// A growable array in the heap, referenced through `Ptr<GcVec<T>>`,
+/// `Root<GcVec<T>>` or `Local<GcVec<T>>`. When stored in an object, walkers
+/// must report the reference to the vector as `GcWalk::Pointer`.
+///
+/// Methods that take the heap may allocate, and thereby collect. The vector
+/// itself survives this, but other `Ptr`s held by the caller may not. The
+/// storage always has room for one more item, so an item that is being
+/// added is stored before any allocation happens. Note that a method called
+/// through a handle dereferences the handle before its arguments are
+/// evaluated, so arguments must not allocate.
+pub struct GcVec<T> {
+	storage: ptr_t,
+	_type: PhantomData<T>
+}
+
+impl<T> GcVec<T> {
+	pub fn len(&self) -> usize {
+		unsafe { *(self.storage as *const usize).offset(1) }
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	
+	pub fn capacity(&self) -> usize {
+		unsafe { *(self.storage as *const usize) }
+	}
+	
+	pub fn push(&mut self, heap: &GcHeap, value: T) {
+		let len = self.len();
+		self.insert(heap, len, value);
+	}
+	
+	pub fn pop(&mut self) -> Option<T> {
+		let len = self.len();
+		if len == 0 {
+			return None;
+		}
+		
+		unsafe {
+			self.set_len(len - 1);
+			
+			Some(ptr::read(self.items().offset((len - 1) as isize)))
+		}
+	}
+	
+	/// Inserts an item at `index`, moving all items after it up by one.
+	pub fn insert(&mut self, heap: &GcHeap, index: usize, value: T) {
+		let len = self.len();
+		assert!(index <= len);
+		
+		unsafe {
+			let items = self.items();
+			
+			ptr::copy(items.offset(index as isize), items.offset(index as isize + 1), len - index);
+			ptr::write(items.offset(index as isize), value);
+			
+			self.set_len(len + 1);
+			
+			if len + 1 == self.capacity() {
+				self.grow(heap, (len + 1) * 2);
+			}
+		}
+	}
+	
+	/// Shortens the vector to `len` items. This has no effect when the
+	/// vector is not longer than that.
+	pub fn truncate(&mut self, len: usize) {
+		if len < self.len() {
+			unsafe { self.set_len(len) };
+		}
+	}
+	
+	/// Ensures there is room for `additional` more items without allocating.
+	pub fn reserve(&mut self, heap: &GcHeap, additional: usize) {
+		let len = self.len();
+		
+		if len + additional >= self.capacity() {
+			unsafe { self.grow(heap, len + additional + 1) };
+		}
+	}
+	
+	unsafe fn items(&self) -> *mut T {
+		transmute(self.storage.offset((2 * size_of::<usize>()) as isize))
+	}
+	
+	unsafe fn set_len(&mut self, len: usize) {
+		*(self.storage as *mut usize).offset(1) = len;
+	}
+	
+	/// Replaces the storage with one of `capacity` items.
+	unsafe fn grow(&mut self, heap: &GcHeap, capacity: usize) {
+		// The allocation may move this vector, so find it back through a root.
+		
+		let ty = GcMemHeader::from_ptr(self.storage).get_type_id();
+		let handle = heap.handles.add(self as *const GcVec<T> as ptr_t);
+		
+		let storage = heap.alloc_vec_storage::<T>(ty, capacity);
+		
+		let this = &mut *(heap.handles.remove(handle) as *mut GcVec<T>);
+		let len = this.len();
+		
+		ptr::copy_nonoverlapping(this.items(), transmute(storage.offset((2 * size_of::<usize>()) as isize)), len);
+		
+		this.storage = storage;
+		this.set_len(len);
+	}
+}
+
+impl<T> Deref for GcVec<T> {
+	type Target = [T];
+	
+	fn deref(&self) -> &[T] {
+		unsafe { slice::from_raw_parts(self.items(), self.len()) }
+	}
+}
+
+impl<T> DerefMut for GcVec<T> {
+	fn deref_mut(&mut self) -> &mut [T] {
+		unsafe { slice::from_raw_parts_mut(self.items(), self.len()) }
+	}
+}
+
+impl GcHeap {
+	/// Allocates an empty vector with room for at least `capacity` items. The
+	/// walker is called for the items with type id `ty`. The vector itself
+	/// also has this type id, but is walked by the collector.
+	pub unsafe fn alloc_vec<T>(&self, ty: u32, capacity: usize) -> Ptr<GcVec<T>> {
+		let vec = self.alloc_with::<GcVec<T>>(ty, HEADER_VECTOR);
+		
+		// Keep the vector alive while its storage is allocated.
+		
+		let handle = self.handles.add(vec.ptr());
+		let storage = self.alloc_vec_storage::<T>(ty, capacity + 1);
+		let mut vec = Ptr::<GcVec<T>>::from_ptr(self.handles.remove(handle));
+		
+		vec.storage = storage;
+		
+		vec
+	}
+	
+	pub fn alloc_vec_root<T>(&self, ty: u32, capacity: usize) -> Root<GcVec<T>> {
+		unsafe { Root::new(self, self.alloc_vec::<T>(ty, capacity)) }
+	}
+	
+	pub fn alloc_vec_local<T>(&self, ty: u32, capacity: usize) -> Local<GcVec<T>> {
+		self.alloc_local_from_ptr(unsafe { self.alloc_vec::<T>(ty, capacity) })
+	}
+	
+	unsafe fn alloc_vec_storage<T>(&self, ty: u32, capacity: usize) -> ptr_t {
+		let ptr = self.alloc_raw(
+			ty,
+			2 * size_of::<usize>() +
+			(size_of::<T>() * capacity) +
+			size_of::<GcMemHeader>()
+		);
+		
+		*GcMemHeader::from_ptr(ptr) = GcMemHeader::new(ty, size_of::<T>(), HEADER_ARRAY | HEADER_VECTOR);
+		*(ptr as *mut usize) = capacity;
+		
+		ptr
+	}
+}
@@ -1,306 +1,1505 @@
-#![allow(dead_code)]
-#![allow(raw_pointer_derive)]
-
-#[macro_use]
-extern crate rjs_gc;
-extern crate time;
-extern crate libc;
-
-use rjs_gc::gc::*;
-use std::mem;
-
-const TYPE_STRUCT   : u32 = 1;
-const TYPE_REF      : u32 = 2;
-const TYPE_CALLBACK : u32 = 3;
-
-struct Stopwatch {
-	started: u64
-}
-
-impl Stopwatch {
-	fn new() -> Stopwatch {
-		Stopwatch {
-			started: time::precise_time_ns()
-		}
-	}
-	
-	fn elapsed(&self) -> u64 {
-		time::precise_time_ns() - self.started
-	}
-	
-	fn elapsed_ms(&self) -> f64 {
-		self.elapsed() as f64 / 1_000_000_000f64
-	}
-}
-
-struct MyStruct {
-	a: i32,
-	b: i32,
-	c: i32
-}
-
-#[derive(Copy, Clone)]
-struct MyStructWithRef {
-	a: Ptr<MyStruct>,
-	b: Ptr<MyStruct>
-}
-
-struct MyMaybeRef {
-	is_ref: bool,
-	value: usize
-}
-
-fn print_stats(heap: &GcHeap) { 
-	println!("STATS: allocated {}, used {}", heap.mem_allocated(), heap.mem_used());
-}
-
-fn main() {
-	bench("Integrity", &|| { integrity() });
-	bench("Callback type", &|| { callback_type() });
-	bench("Arrays", &|| { arrays() });
-	bench("Large allocs", &|| { large_allocs() });
-	bench("Many allocs", &|| { many_allocs() });
-}
-
-fn integrity() {
-	let heap = create_heap();
-	
-	let item = {
-		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
-			
-		result.a = alloc_struct(&heap, 1, 2, 3);
-		result.b = alloc_struct(&heap, 4, 5, 6);
-		
-		result
-	};
-	
-	print_stats(&heap);
-	
-	heap.gc();
-	
-	print_stats(&heap);
-	
-	assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
-	
-	print_stats(&heap);
-	
-	heap.gc();
-	
-	print_stats(&heap);
-	
-	assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
-}
-
-fn arrays() {
-	let heap = create_heap();
-	
-	let mut array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 10);
-	
-	for i in 0..array.len() {
-		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
-		
-		result.a = alloc_struct(&heap, 1, 2, 3);
-		result.b = alloc_struct(&heap, 4, 5, 6);
-		
-		array[i] = *result;
-	}
-	
-	print_stats(&heap);
-	
-	heap.gc();
-	
-	print_stats(&heap);
-	
-	for i in 0..array.len() {
-		let item = &array[i];
-		
-		assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
-	}
-}
-
-struct Walker;
-
-impl Walker {
-	fn new() -> Walker {
-		Walker
-	}
-}
-
-impl GcWalker for Walker {
-	fn walk(&self, ty: u32, ptr: ptr_t, index: u32) -> GcWalk {
-		match ty {
-			TYPE_STRUCT => GcWalk::Skip,
-			TYPE_REF => GcWalk::Pointer,
-			TYPE_CALLBACK => {
-				match index {
-					0 => GcWalk::Skip,
-					1 => {
-						// The boolean at the start indicates whether this is a reference.
-						
-						let is_ref = unsafe { *mem::transmute::<_, &bool>(ptr) };
-						if is_ref { GcWalk::Pointer } else { GcWalk::Skip }
-					}
-					_ => GcWalk::End
-				}
-			}
-			_ => panic!("{}", ty)
-		}
-	}
-}
-
-fn create_heap() -> GcHeap {
-	GcHeap::new(Box::new(Walker::new()), GcOpts::default())
-}
-
-fn bench(msg: &str, callback: &Fn()) {
-	println!("");
-	println!("==> Running {}", msg);
-	println!("");
-	
-	let stopwatch = Stopwatch::new();
-	callback();
-	
-	println!("");
-	println!("==> {} took {}", msg, stopwatch.elapsed_ms());
-	println!("");
-}
-
-fn alloc_struct(heap: &GcHeap, a: i32, b: i32, c: i32) -> Ptr<MyStruct> {
-	unsafe {
-		let mut result = heap.alloc(TYPE_STRUCT);
-		
-		*result = MyStruct {
-			a: a,
-			b: b,
-			c: c
-		};
-		
-		result
-	}
-}
-
-fn large_allocs() {
-	let heap = create_heap();
-	
-	let mut small = Vec::new();
-	
-	for _ in 0..400000 {
-		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
-		
-		result.a = alloc_struct(&heap, 1, 2, 3);
-		result.b = alloc_struct(&heap, 4, 5, 6);
-		
-		small.push(Some(result));
-	}
-	
-//	println!("after init");
-//	print_stats(&heap);
-	
-	heap.gc();
-	
-//	println!("after init gc");
-//	print_stats(&heap);
-
-	for _ in 0..100 {
-		for i in 0..100 {
-			let mut offset = i;
-			let mut inc = 1;
-			
-			while offset < small.len() {
-				let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
-			
-				result.a = alloc_struct(&heap, 1, 2, 3);
-				result.b = alloc_struct(&heap, 4, 5, 6);
-				
-				small[offset] = Some(result);
-				
-				offset += inc;
-				inc += 1;
-			}
-		}
-	}
-	
-//	println!("after replace");
-//	print_stats(&heap);
-
-	heap.gc();
-	
-//	println!("after replace gc");
-//	print_stats(&heap);
-	
-	for i in (0..4000).rev() {
-		small[i * 10] = None;
-	}
-	
-//	println!("after remove");
-//	print_stats(&heap);
-
-	heap.gc();
-	
-//	println!("after remove gc");
-	print_stats(&heap);
-}
-
-fn many_allocs() {
-	let heap = create_heap();
-	
-	for _ in 0..10 {
-		print_stats(&heap);
-		
-		let _scope = heap.new_local_scope();
-		
-		for _ in 0..400000 {
-			let mut result = heap.alloc_local::<MyStructWithRef>(TYPE_REF);
-			
-			result.a = alloc_struct(&heap, 1, 2, 3);
-			result.b = alloc_struct(&heap, 4, 5, 6);
-		}
-	}
-	
-	heap.gc();
-	
-	print_stats(&heap);
-}
-
-fn callback_type() {
-	let heap = create_heap();
-	
-	{
-		// Test without reference.
-		
-		let _scope = heap.new_local_scope();
-		
-		let mut result = heap.alloc_local(TYPE_CALLBACK);
-		
-		*result = MyMaybeRef {
-			is_ref: false,
-			value: 0
-		};
-		
-		heap.gc();
-		
-		print_stats(&heap);
-	}
-	
-	{
-		// Test with reference.
-		
-		let _scope = heap.new_local_scope();
-		
-		let mut result = heap.alloc_local(TYPE_CALLBACK);
-		
-		*result = MyMaybeRef {
-			is_ref: true,
-			value: alloc_struct(&heap, 1, 2, 3).ptr() as usize
-		};
-		
-		heap.gc();
-		
-		print_stats(&heap);
-		
-		let value : Ptr<MyStruct> = Ptr::from_ptr(result.value as ptr_t);
-		let my_struct = &*value;
-		
-		assert_eq!(1 + 2 + 3, my_struct.a + my_struct.b + my_struct.c);
-	}
-}
+#![allow(dead_code)]
+#![allow(raw_pointer_derive)]
+
+#[macro_use]
+extern crate rjs_gc;
+extern crate time;
+extern crate libc;
+
+use rjs_gc::gc::*;
+use std::mem;
+use std::thread;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hint::black_box;
+
+const TYPE_STRUCT   : u32 = 1;
+const TYPE_REF      : u32 = 2;
+const TYPE_CALLBACK : u32 = 3;
+const TYPE_FIELD    : u32 = 4;
+const TYPE_VALUE    : u32 = 5;
+// Not known to the walker, so objects of this type must never be walked.
+const TYPE_PLAIN    : u32 = 6;
+const TYPE_LINK     : u32 = 7;
+const TYPE_ENTRY    : u32 = 8;
+// Strings are never walked, so the walker does not know this type either.
+const TYPE_STRING   : u32 = 0x7f;
+
+// Values are NaN boxed. Pointers are stored in the low bits of a NaN that no
+// arithmetic produces.
+const VALUE_TAG_MASK    : usize = 0xffff_0000_0000_0000;
+const VALUE_TAG_POINTER : usize = 0xfffc_0000_0000_0000;
+
+struct Stopwatch {
+	started: u64
+}
+
+impl Stopwatch {
+	fn new() -> Stopwatch {
+		Stopwatch {
+			started: time::precise_time_ns()
+		}
+	}
+	
+	fn elapsed(&self) -> u64 {
+		time::precise_time_ns() - self.started
+	}
+	
+	fn elapsed_ms(&self) -> f64 {
+		self.elapsed() as f64 / 1_000_000_000f64
+	}
+}
+
+struct MyStruct {
+	a: i32,
+	b: i32,
+	c: i32
+}
+
+#[derive(Copy, Clone)]
+struct MyStructWithRef {
+	a: Ptr<MyStruct>,
+	b: Ptr<MyStruct>
+}
+
+#[repr(C)]
+struct MyLink {
+	target: Ptr<MyStruct>,
+	tag: u32
+}
+
+struct MyFieldRef {
+	field: FieldPtr<i32>
+}
+
+#[derive(Copy, Clone)]
+struct Value {
+	bits: usize
+}
+
+impl Value {
+	fn number(value: f64) -> Value {
+		Value { bits: value.to_bits() as usize }
+	}
+	
+	fn object(ptr: Ptr<MyStruct>) -> Value {
+		Value { bits: VALUE_TAG_POINTER | ptr.ptr() as usize }
+	}
+	
+	fn as_number(&self) -> Option<f64> {
+		if self.bits & VALUE_TAG_MASK == VALUE_TAG_POINTER { None } else { Some(f64::from_bits(self.bits as u64)) }
+	}
+	
+	fn as_object(&self) -> Option<Ptr<MyStruct>> {
+		if self.bits & VALUE_TAG_MASK == VALUE_TAG_POINTER { Some(Ptr::from_ptr((self.bits & !VALUE_TAG_MASK) as ptr_t)) } else { None }
+	}
+}
+
+// The walker reads the flag from the first word.
+#[repr(C)]
+struct MyMaybeRef {
+	is_ref: bool,
+	value: usize
+}
+
+fn print_stats(heap: &GcHeap) { 
+	println!("STATS: allocated {}, used {}", heap.mem_allocated(), heap.mem_used());
+}
+
+fn main() {
+	bench("Integrity", &|| { integrity() });
+	bench("Callback type", &|| { callback_type() });
+	bench("Arrays", &|| { arrays() });
+	bench("Census", &|| { census() });
+	bench("Heap snapshot", &|| { heap_snapshot() });
+	bench("Retaining path", &|| { retaining_path() });
+	bench("Dot", &|| { dot() });
+	bench("Profiler", &|| { profiler() });
+	bench("Tracer", &|| { tracer() });
+	bench("Heap sizing", &|| { heap_sizing() });
+	bench("Triggers", &|| { triggers() });
+	bench("Collection kinds", &|| { collection_kinds() });
+	bench("Options", &|| { options() });
+	bench("Shared heap", &|| { shared_heap() });
+	bench("Allocation buffers", &|| { allocation_buffers() });
+	bench("Isolate", &|| { isolate() });
+	bench("External references", &|| { external_references() });
+	bench("Teardown", &|| { teardown() });
+	bench("Conservative stack", &|| { conservative_stack() });
+	bench("Interior pointers", &|| { interior_pointers() });
+	bench("Tagged values", &|| { tagged_values() });
+	bench("Plain arrays", &|| { plain_arrays() });
+	bench("Array packing", &|| { array_packing() });
+	bench("Strings", &|| { strings() });
+	bench("Vectors", &|| { vectors() });
+	bench("Hash maps", &|| { hash_maps() });
+	bench("Large allocs", &|| { large_allocs() });
+	bench("Many allocs", &|| { many_allocs() });
+}
+
+fn integrity() {
+	let heap = create_heap();
+	
+	let item = {
+		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+			
+		result.a = alloc_struct(&heap, 1, 2, 3);
+		result.b = alloc_struct(&heap, 4, 5, 6);
+		
+		result
+	};
+	
+	print_stats(&heap);
+	
+	heap.gc();
+	
+	print_stats(&heap);
+	
+	assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
+	
+	print_stats(&heap);
+	
+	heap.gc();
+	
+	print_stats(&heap);
+	
+	assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
+}
+
+fn arrays() {
+	let heap = create_heap();
+	
+	let mut array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 10);
+	
+	for i in 0..array.len() {
+		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+		
+		result.a = alloc_struct(&heap, 1, 2, 3);
+		result.b = alloc_struct(&heap, 4, 5, 6);
+		
+		array[i] = *result;
+	}
+	
+	print_stats(&heap);
+	
+	heap.gc();
+	
+	print_stats(&heap);
+	
+	for i in 0..array.len() {
+		let item = &array[i];
+		
+		assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
+	}
+}
+
+fn census() {
+	let heap = create_heap();
+	
+	let mut array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 10);
+	
+	for i in 0..array.len() {
+		array[i].a = alloc_struct(&heap, 1, 2, 3);
+		array[i].b = alloc_struct(&heap, 4, 5, 6);
+	}
+	
+	let census = heap.census();
+	
+	assert_eq!(census.get(TYPE_REF).objects, 1);
+	assert_eq!(census.get(TYPE_REF).arrays, 1);
+	assert_eq!(census.get(TYPE_STRUCT).objects, 20);
+	assert_eq!(census.get(TYPE_STRUCT).arrays, 0);
+	
+	heap.set_census_on_gc(true);
+	heap.gc();
+	
+	let last = heap.last_census().unwrap();
+	
+	assert_eq!(last.total_objects(), census.total_objects());
+	assert_eq!(last.total_bytes(), census.total_bytes());
+}
+
+fn heap_snapshot() {
+	let heap = create_heap();
+	
+	let mut item = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+	item.a = alloc_struct(&heap, 1, 2, 3);
+	item.b = item.a;
+	
+	let mut snapshot = Vec::new();
+	heap.write_heap_snapshot(&mut snapshot, None).unwrap();
+	
+	let snapshot = String::from_utf8(snapshot).unwrap();
+	
+	assert!(snapshot.contains("\"node_count\":5,\"edge_count\":5,"));
+}
+
+fn retaining_path() {
+	let heap = create_heap();
+	
+	let mut array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 10);
+	array[5].b = alloc_struct(&heap, 1, 2, 3);
+	
+	let target = unsafe { Root::new(&heap, array[5].b) };
+	
+	assert_eq!(heap.retaining_path(&target).unwrap().root, GcRoot::Handle(1));
+	
+	let path = target.retaining_path(&heap).unwrap();
+	
+	assert_eq!(path.root, GcRoot::Handle(0));
+	assert_eq!(path.edges.len(), 1);
+	assert_eq!(path.edges[0].from, array.as_ptr().ptr());
+	assert_eq!(path.edges[0].offset, mem::size_of::<usize>() + 5 * mem::size_of::<MyStructWithRef>() + mem::size_of::<usize>());
+	
+	array[5].b = Ptr::null();
+	
+	assert!(target.retaining_path(&heap).is_none());
+}
+
+fn dot() {
+	let heap = create_heap();
+	
+	let _scope = heap.new_local_scope();
+	
+	let mut item = heap.alloc_local::<MyStructWithRef>(TYPE_REF);
+	item.a = alloc_struct(&heap, 1, 2, 3);
+	
+	let mut dot = Vec::new();
+	heap.dump_dot(&mut dot).unwrap();
+	
+	let dot = String::from_utf8(dot).unwrap();
+	
+	assert!(dot.starts_with("digraph heap {"));
+	assert!(dot.contains(&format!("\"l0_0\" -> \"{:?}\";", item.as_ptr().ptr())));
+	assert!(dot.contains(&format!("\"{:?}\" -> \"{:?}\" [label=\"@0\"];", item.as_ptr().ptr(), item.a.ptr())));
+}
+
+fn profiler() {
+	let heap = create_heap();
+	
+	heap.start_profiling(GcProfilerOpts {
+		interval: 1024,
+		backtraces: false
+	});
+	
+	heap.set_allocation_tag(Some("garbage".to_string()));
+	
+	{
+		let _scope = heap.new_local_scope();
+		
+		for _ in 0..1000 {
+			heap.alloc_local::<MyStructWithRef>(TYPE_REF);
+		}
+	}
+	
+	heap.set_allocation_tag(Some("live".to_string()));
+	
+	let mut live = Vec::new();
+	
+	for _ in 0..1000 {
+		live.push(heap.alloc_root::<MyStructWithRef>(TYPE_REF));
+	}
+	
+	heap.gc();
+	
+	let mut report = Vec::new();
+	heap.write_profile_report(&mut report).unwrap();
+	print!("{}", String::from_utf8(report).unwrap());
+	
+	let profiler = heap.stop_profiling().unwrap();
+	
+	for sample in profiler.samples() {
+		assert_eq!(sample.is_alive(), sample.site == "live");
+		assert_eq!(sample.ty, TYPE_REF);
+	}
+}
+
+fn tracer() {
+	let heap = create_heap();
+	
+	heap.start_tracing(1, 1);
+	
+	let _item = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+	
+	heap.gc();
+	heap.gc();
+	
+	let mut trace = Vec::new();
+	heap.stop_tracing().unwrap().write_json(&mut trace).unwrap();
+	
+	let trace = String::from_utf8(trace).unwrap();
+	
+	assert_eq!(trace.matches("\"name\":\"gc\"").count(), 2);
+	assert_eq!(trace.matches("\"name\":\"root handles\"").count(), 2);
+	assert_eq!(trace.matches("\"name\":\"scan\"").count(), 2);
+}
+
+fn heap_sizing() {
+	let mut opts = GcOpts::default();
+	opts.initial_heap = 1024 * 1024;
+	opts.sizing = Some(Box::new(FixedHeapSizing));
+	
+	let heap = GcHeap::new(Box::new(Walker::new()), opts);
+	
+	for _ in 0..100 {
+		let _scope = heap.new_local_scope();
+		
+		for _ in 0..5000 {
+			let mut result = heap.alloc_local::<MyStructWithRef>(TYPE_REF);
+			
+			result.a = alloc_struct(&heap, 1, 2, 3);
+		}
+	}
+	
+	assert!(heap.stats().collections > 0);
+	assert_eq!(heap.mem_allocated(), 2 * 1024 * 1024);
+	
+	let mut opts = GcOpts::default();
+	opts.initial_heap = 1024 * 1024;
+	opts.sizing = Some(Box::new(OccupancyHeapSizing::new(0.5, 1024 * 1024, 64 * 1024 * 1024)));
+	
+	let heap = GcHeap::new(Box::new(Walker::new()), opts);
+	
+	{
+		let mut live = Vec::new();
+		
+		for _ in 0..100000 {
+			live.push(heap.alloc_root::<MyStructWithRef>(TYPE_REF));
+		}
+		
+		heap.gc();
+		
+		assert!(heap.mem_used() * 2 <= heap.mem_allocated());
+	}
+	
+	let allocated = heap.mem_allocated();
+	
+	heap.gc();
+	heap.gc();
+	
+	assert!(heap.mem_allocated() < allocated);
+}
+
+fn alloc_garbage(heap: &GcHeap, count: usize) {
+	let _scope = heap.new_local_scope();
+	
+	for _ in 0..count {
+		heap.alloc_local::<MyStructWithRef>(TYPE_REF);
+	}
+}
+
+/// Collects a few times with garbage in between, so surviving objects are
+/// copied more than once.
+fn churn(heap: &GcHeap) {
+	for _ in 0..3 {
+		alloc_garbage(heap, 10000);
+		heap.gc();
+	}
+}
+
+fn triggers() {
+	let mut opts = GcOpts::default();
+	opts.allocation_budget = Some(1024 * 1024);
+	
+	let heap = GcHeap::new(Box::new(Walker::new()), opts);
+	
+	alloc_garbage(&heap, 100000);
+	
+	assert!(heap.stats().collections >= 2);
+	
+	let heap = create_heap();
+	
+	heap.set_gc_trigger(Some(Box::new(|info: &GcTriggerInfo| info.allocated_since_gc >= 1024 * 1024)));
+	
+	alloc_garbage(&heap, 100000);
+	
+	let collections = heap.stats().collections;
+	assert!(collections >= 2);
+	
+	heap.set_gc_trigger(None);
+	
+	alloc_garbage(&heap, 100000);
+	
+	assert_eq!(heap.stats().collections, collections);
+	
+	let heap = create_heap();
+	
+	assert!(!heap.collect_if_worthwhile(time::precise_time_ns() + 1_000_000_000));
+	
+	alloc_garbage(&heap, 200000);
+	
+	assert!(!heap.collect_if_worthwhile(time::precise_time_ns()));
+	assert!(heap.collect_if_worthwhile(time::precise_time_ns() + 1_000_000_000));
+	assert_eq!(heap.stats().collections, 1);
+}
+
+fn collection_kinds() {
+	let heap = create_heap();
+	
+	let _item = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+	
+	alloc_garbage(&heap, 100000);
+	
+	heap.gc_with(GcRequest::new(GcKind::Minor, GcReason::Idle));
+	
+	let allocated = heap.mem_allocated();
+	
+	heap.gc_with(GcRequest::new(GcKind::Shrinking, GcReason::LowMemory));
+	
+	assert!(heap.mem_allocated() < allocated);
+	
+	let stats = heap.stats();
+	
+	assert_eq!(stats.collections, 2);
+	assert_eq!(stats.major_collections, 1);
+	assert_eq!(stats.shrinking_collections, 1);
+	
+	// Only the pages that hold the survivors are kept.
+	
+	alloc_garbage(&heap, 100000);
+	heap.gc_with(GcRequest::new(GcKind::Shrinking, GcReason::LowMemory));
+	
+	assert!(heap.mem_allocated() < heap.mem_used() + os::PAGE_SIZE);
+	
+	alloc_garbage(&heap, 100000);
+}
+
+fn options() {
+	assert_eq!(GcOpts::builder().initial_heap(1000).build().err(), Some(GcOptsError::InvalidInitialHeap(1000)));
+	assert_eq!(GcOpts::builder().slow_growth_factor(1f64).build().err(), Some(GcOptsError::InvalidSlowGrowthFactor(1f64)));
+	assert_eq!(GcOpts::builder().max_heap(1024 * 1024).build().err(), Some(GcOptsError::InvalidMaxHeap(1024 * 1024)));
+	assert_eq!(GcOpts::builder().allocation_budget(0).build().err(), Some(GcOptsError::InvalidAllocationBudget(0)));
+	
+	std::env::set_var("RJS_GC_INITIAL_HEAP", "4M");
+	std::env::set_var("RJS_GC_MAX_HEAP", "64M");
+	
+	let opts = GcOpts::from_env().unwrap();
+	
+	assert_eq!(opts.initial_heap, 4 * 1024 * 1024);
+	assert_eq!(opts.max_heap, Some(64 * 1024 * 1024));
+	
+	std::env::set_var("RJS_GC_SLOW_GROWTH_FACTOR", "fast");
+	
+	assert!(GcOpts::from_env().is_err());
+	
+	std::env::remove_var("RJS_GC_INITIAL_HEAP");
+	std::env::remove_var("RJS_GC_MAX_HEAP");
+	std::env::remove_var("RJS_GC_SLOW_GROWTH_FACTOR");
+	
+	let heap = GcHeap::new(Box::new(Walker::new()), GcOpts::builder().initial_heap(1024 * 1024).max_heap(4 * 1024 * 1024).build().unwrap());
+	
+	for _ in 0..100 {
+		alloc_garbage(&heap, 10000);
+	}
+	
+	assert!(heap.stats().collections > 0);
+	assert!(heap.mem_allocated() <= 8 * 1024 * 1024);
+}
+
+fn shared_heap() {
+	let heap = SharedGcHeap::new(Box::new(Walker::new()), GcOpts::builder().initial_heap(1024 * 1024).build().unwrap());
+	
+	let threads = (0..4).map(|i| {
+		let heap = heap.clone();
+		
+		thread::spawn(move || {
+			let mutator = heap.mutator();
+			
+			if i == 0 {
+				mutator.add_listener(Box::new(SafepointListener { heap: &mutator }));
+			}
+			
+			let mut array = mutator.alloc_array_root::<MyStructWithRef>(TYPE_REF, 100);
+			
+			for j in 0..array.len() {
+				let mut result = mutator.alloc_root::<MyStructWithRef>(TYPE_REF);
+				
+				result.a = alloc_struct(&mutator, i, j as i32, 1);
+				result.b = alloc_struct(&mutator, i, j as i32, 2);
+				
+				array[j] = *result;
+				
+				alloc_garbage(&mutator, 1000);
+				
+				if j % 25 == 0 {
+					unsafe { mutator.safe_region(|| thread::sleep(std::time::Duration::from_millis(1))) };
+				}
+				if i == 0 && j % 10 == 0 {
+					mutator.gc();
+				}
+			}
+			
+			for j in 0..array.len() {
+				let item = &array[j];
+				
+				assert_eq!((item.a.a, item.a.b, item.a.c), (i, j as i32, 1));
+				assert_eq!((item.b.a, item.b.b, item.b.c), (i, j as i32, 2));
+			}
+			
+			mutator.stats().collections
+		})
+	}).collect::<Vec<_>>();
+	
+	let collections = threads.into_iter().fold(0, |collections, thread| collections + thread.join().unwrap());
+	
+	assert!(collections >= 10);
+}
+
+/// Reaches a safepoint and allocates while the world is still stopped.
+struct SafepointListener {
+	heap: *const GcHeap
+}
+
+impl GcListener for SafepointListener {
+	fn after_gc(&self, _stats: &GcCollectionStats) {
+		let heap = unsafe { &*self.heap };
+		
+		heap.safepoint();
+		alloc_struct(heap, 0, 0, 0);
+	}
+}
+
+fn allocation_buffers() {
+	let heap = create_heap();
+	
+	// Mix small objects, which are allocated from the buffer, with arrays that
+	// are too large for it.
+	
+	let mut items = Vec::new();
+	let mut arrays = Vec::new();
+	
+	for i in 0..10000 {
+		let mut item = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+		item.a = alloc_struct(&heap, i, 1, 2);
+		items.push(item);
+		
+		if i % 1000 == 0 {
+			let array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 2000);
+			assert!(array.iter().all(|item| item.a.as_ptr().is_null()));
+			arrays.push(array);
+		}
+	}
+	
+	heap.gc();
+	
+	alloc_garbage(&heap, 100000);
+	
+	for (i, item) in items.iter().enumerate() {
+		assert_eq!((item.a.a, item.a.b, item.a.c), (i as i32, 1, 2));
+		assert!(item.b.as_ptr().is_null());
+	}
+	
+	assert_eq!(arrays.len(), 10);
+}
+
+struct IsolateState {
+	items: Vec<Root<MyStructWithRef>>
+}
+
+unsafe impl IsolateData for IsolateState {
+	fn each_heap_id(&self, f: &mut FnMut(GcHeapId)) {
+		self.items.each_heap_id(f);
+	}
+}
+
+fn isolate() {
+	let mut isolate = Isolate::new(Box::new(Walker::new()), GcOpts::default(), |_| {
+		IsolateState {
+			items: Vec::new()
+		}
+	});
+	
+	// Move the isolate to a new thread for every request.
+	
+	for i in 0..10 {
+		isolate = thread::spawn(move || {
+			isolate.enter(|heap, state| {
+				let mut item = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+				item.a = alloc_struct(heap, i, 1, 2);
+				state.items.push(item);
+				
+				alloc_garbage(heap, 10000);
+				heap.gc();
+				
+				for (j, item) in state.items.iter().enumerate() {
+					assert_eq!((item.a.a, item.a.b, item.a.c), (j as i32, 1, 2));
+				}
+			});
+			
+			isolate
+		}).join().unwrap();
+	}
+	
+	assert_eq!(isolate.enter(|heap, _| heap.stats().collections), 10);
+	
+	// Listeners of an isolated heap must be Send, so they are added through the
+	// isolate.
+	
+	isolate.add_listener(Box::new(PrintListener));
+	
+	let result = thread::spawn(move || {
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+			isolate.enter(|heap, _| heap.add_listener(Box::new(PrintListener)));
+		}));
+		assert!(result.is_err());
+		
+		isolate
+	}).join();
+	
+	let mut isolate = result.unwrap();
+	
+	// A root of another heap must not be stored in the data.
+	
+	let result = thread::spawn(|| {
+		let mut foreign = Isolate::new(Box::new(Walker::new()), GcOpts::default(), |_| {
+			IsolateState {
+				items: Vec::new()
+			}
+		});
+		
+		foreign.enter(|_, state| {
+			let other = Box::leak(Box::new(create_heap()));
+			state.items.push(other.alloc_root::<MyStructWithRef>(TYPE_REF));
+		});
+	}).join();
+	
+	assert!(result.is_err());
+	
+	let result = thread::spawn(move || {
+		isolate.enter(|heap, _| {
+			let _scope = heap.new_local_scope();
+			heap.alloc_local::<MyStruct>(TYPE_STRUCT);
+			
+			// Leaking the scope out of the isolate is not allowed.
+			
+			std::mem::forget(_scope);
+		});
+	}).join();
+	
+	assert!(result.is_err());
+}
+
+struct MyExternalRef {
+	external: External<MyStruct>
+}
+
+fn external_references() {
+	let a = create_heap();
+	let b = create_heap();
+	
+	assert!(a.id() != b.id());
+	
+	let target = {
+		let target = alloc_struct(&b, 1, 2, 3);
+		b.new_external(&target)
+	};
+	
+	// The external reference is plain data, so it can be stored in an object of
+	// another heap.
+	
+	let mut holder = a.alloc_root::<MyExternalRef>(TYPE_STRUCT);
+	holder.external = target;
+	
+	alloc_garbage(&b, 100000);
+	a.gc();
+	b.gc();
+	
+	let object = holder.external.get(&b);
+	assert_eq!((object.a, object.b, object.c), (1, 2, 3));
+	assert_eq!(holder.external.heap_id(), b.id());
+	
+	b.release_external(holder.external);
+	
+	// A copy of a released reference does not see the root that reuses its
+	// handle.
+	
+	let _reused = b.alloc_root::<MyStruct>(TYPE_STRUCT);
+	
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| holder.external.get(&b)));
+	assert!(result.is_err());
+	
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| b.release_external(holder.external)));
+	assert!(result.is_err());
+	
+	if cfg!(debug_assertions) {
+		let root = b.alloc_root::<MyStruct>(TYPE_STRUCT);
+		
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+			let _scope = a.new_local_scope();
+			root.as_local(&a);
+		}));
+		
+		assert!(result.is_err());
+		
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+			root.as_ptr().as_local(&a);
+		}));
+		
+		assert!(result.is_err());
+	}
+}
+
+struct LeakListener {
+	leaked: Rc<RefCell<Vec<GcLeakedRoot>>>
+}
+
+impl GcListener for LeakListener {
+	fn heap_dropped(&self, leaked: &[GcLeakedRoot]) {
+		self.leaked.borrow_mut().extend_from_slice(leaked);
+	}
+}
+
+fn teardown() {
+	let leaked = Rc::new(RefCell::new(Vec::new()));
+	
+	let heap = create_heap();
+	heap.add_listener(Box::new(LeakListener { leaked: leaked.clone() }));
+	
+	let mut item = heap.alloc_root::<MyStruct>(TYPE_STRUCT);
+	item.a = 7;
+	let array = heap.alloc_array_root::<MyStructWithRef>(TYPE_REF, 3);
+	let dropped = heap.alloc_root::<MyStruct>(TYPE_STRUCT);
+	
+	assert_eq!(item.try_get().map(|item| item.a), Some(7));
+	
+	drop(dropped);
+	drop(heap);
+	
+	{
+		let leaked = leaked.borrow();
+		
+		assert_eq!(leaked.len(), 2);
+		assert_eq!(leaked[0].object.ty, TYPE_STRUCT);
+		assert!(leaked[1].object.is_array);
+		assert_eq!(leaked[1].object.len, 3);
+	}
+	
+	assert!(item.try_get().is_none());
+	assert!(array.try_get().is_none());
+	
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| item.a));
+	assert!(result.is_err());
+	
+	// Cloning and dropping roots of a dropped heap is allowed.
+	
+	let clone = item.clone();
+	assert!(clone.try_get().is_none());
+}
+
+fn conservative_stack() {
+	let opts = || GcOpts::builder().initial_heap(1024 * 1024).conservative_stack(true).build().unwrap();
+	
+	let heap = GcHeap::new(Box::new(Walker::new()), opts());
+	
+	// This object is only referenced from the stack, so it is pinned.
+	
+	let item = unsafe {
+		let mut item = heap.alloc::<MyStructWithRef>(TYPE_REF);
+		
+		item.a = alloc_struct(&heap, 1, 2, 3);
+		item.b = alloc_struct(&heap, 4, 5, 6);
+		
+		item
+	};
+	let address = item.ptr();
+	black_box(&item);
+	
+	// Handles are still traced precisely.
+	
+	let mut root = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+	root.a = alloc_struct(&heap, 7, 8, 9);
+	
+	for _ in 0..10 {
+		alloc_garbage(&heap, 10000);
+		heap.gc();
+	}
+	
+	let item = black_box(item);
+	assert_eq!(item.ptr(), address);
+	assert_eq!(item.a.a + item.a.b + item.a.c + item.b.a + item.b.b + item.b.c, 21);
+	assert_eq!((root.a.a, root.a.b, root.a.c), (7, 8, 9));
+	
+	// The stacks of parked threads of a shared heap are scanned too.
+	
+	let heap = SharedGcHeap::new(Box::new(Walker::new()), opts());
+	let (parked_tx, parked_rx) = std::sync::mpsc::channel();
+	let (collected_tx, collected_rx) = std::sync::mpsc::channel::<()>();
+	
+	let thread = {
+		let heap = heap.clone();
+		
+		thread::spawn(move || {
+			let mutator = heap.mutator();
+			
+			let item = alloc_struct(&mutator, 1, 2, 3);
+			let address = item.ptr() as usize;
+			black_box(&item);
+			
+			unsafe {
+				mutator.safe_region(|| {
+					parked_tx.send(()).unwrap();
+					collected_rx.recv().unwrap();
+				})
+			};
+			
+			let item = black_box(item);
+			assert_eq!(item.ptr() as usize, address);
+			assert_eq!((item.a, item.b, item.c), (1, 2, 3));
+		})
+	};
+	
+	let mutator = heap.mutator();
+	parked_rx.recv().unwrap();
+	
+	for _ in 0..10 {
+		alloc_garbage(&mutator, 10000);
+		mutator.gc();
+	}
+	
+	collected_tx.send(()).unwrap();
+	thread.join().unwrap();
+	
+	// The allocation buffer of a dropped mutator is retired, so the from space
+	// can still be walked for the stacks of the others.
+	
+	let heap = SharedGcHeap::new(Box::new(Walker::new()), opts());
+	
+	{
+		let mutator = heap.mutator();
+		mutator.alloc_root::<MyStruct>(TYPE_STRUCT);
+	}
+	
+	let mutator = heap.mutator();
+	let item = alloc_struct(&mutator, 4, 5, 6);
+	black_box(&item);
+	
+	mutator.gc();
+	
+	let item = black_box(item);
+	assert_eq!((item.a, item.b, item.c), (4, 5, 6));
+}
+
+fn interior_pointers() {
+	let heap = create_heap();
+	
+	let mut array = heap.alloc_array_root::<MyStruct>(TYPE_STRUCT, 10);
+	for i in 0..array.len() {
+		array[i] = MyStruct { a: i as i32, b: 0, c: 0 };
+	}
+	
+	let mut item = array.as_ptr().item_ptr(3).as_root(&heap);
+	
+	// The struct is only referenced through a field pointer stored in an
+	// object.
+	
+	let mut holder = heap.alloc_root::<MyFieldRef>(TYPE_FIELD);
+	holder.field = alloc_struct(&heap, 1, 2, 3).field(|item| &item.b);
+	
+	churn(&heap);
+	
+	assert_eq!(item.a, 3);
+	assert_eq!(*holder.field, 2);
+	
+	item.b = 7;
+	*holder.field = 8;
+	
+	assert_eq!(array[3].b, 7);
+	assert_eq!(holder.field.offset(), 4);
+	
+	// The field root keeps the whole array alive.
+	
+	drop(array);
+	heap.gc();
+	
+	assert_eq!((item.a, item.b), (3, 7));
+	assert_eq!(*holder.field, 8);
+}
+
+fn tagged_values() {
+	let heap = create_heap();
+	
+	let mut values = heap.alloc_array_root::<Value>(TYPE_VALUE, 20);
+	for i in 0..values.len() {
+		values[i] = if i % 2 == 0 {
+			Value::number(i as f64 + 0.5)
+		} else {
+			Value::object(alloc_struct(&heap, i as i32, 0, 0))
+		};
+	}
+	values[0] = Value::number(std::f64::NAN);
+	
+	let before = values[1].as_object().unwrap().ptr();
+	
+	churn(&heap);
+	
+	assert!(values[1].as_object().unwrap().ptr() != before);
+	assert!(values[0].as_number().unwrap().is_nan());
+	
+	for i in 1..values.len() {
+		if i % 2 == 0 {
+			assert_eq!(values[i].as_number(), Some(i as f64 + 0.5));
+		} else {
+			assert_eq!(values[i].as_object().unwrap().a, i as i32);
+		}
+	}
+	
+	assert_eq!(heap.census().get(TYPE_STRUCT).objects, 10);
+}
+
+fn plain_arrays() {
+	let heap = create_heap();
+	
+	let mut bytes = heap.alloc_bytes_root(TYPE_PLAIN, 13);
+	for i in 0..bytes.len() {
+		bytes[i] = i as u8 * 3;
+	}
+	
+	let mut numbers = unsafe { heap.alloc_array_plain_root::<f64>(TYPE_PLAIN, 5) };
+	for i in 0..numbers.len() {
+		numbers[i] = i as f64 / 4f64;
+	}
+	
+	let mut shorts = unsafe { heap.alloc_array_plain_root::<u16>(TYPE_PLAIN, 7) };
+	for i in 0..shorts.len() {
+		shorts[i] = 0xfff0 + i as u16;
+	}
+	
+	churn(&heap);
+	
+	for i in 0..bytes.len() {
+		assert_eq!(bytes[i], i as u8 * 3);
+	}
+	for i in 0..numbers.len() {
+		assert_eq!(numbers[i], i as f64 / 4f64);
+	}
+	for i in 0..shorts.len() {
+		assert_eq!(shorts[i], 0xfff0 + i as u16);
+	}
+	
+	// The items are not padded to a word.
+	
+	let census = heap.census();
+	let plain = census.get(TYPE_PLAIN);
+	
+	assert_eq!(plain.arrays, 3);
+	assert_eq!(plain.bytes, (8 + 16 + 8) + (8 + 48) + (8 + 24));
+	
+	// Objects are not limited to 16 MB.
+	
+	const LARGE : usize = 20 * 1024 * 1024;
+	
+	let mut large = heap.alloc_root::<[u8; LARGE]>(TYPE_STRUCT);
+	large[LARGE - 1] = 7;
+	heap.gc();
+	
+	assert_eq!(large[LARGE - 1], 7);
+}
+
+fn array_packing() {
+	let heap = create_heap();
+	
+	// Items smaller than a word, or that are not a whole number of words, are
+	// never walked, so these can use a type the walker does not know.
+	
+	let mut bytes = heap.alloc_array_root::<u8>(TYPE_PLAIN, 13);
+	let mut shorts = heap.alloc_array_root::<u16>(TYPE_PLAIN, 7);
+	let mut ints = heap.alloc_array_root::<u32>(TYPE_PLAIN, 5);
+	let mut triples = heap.alloc_array_root::<[u8; 3]>(TYPE_PLAIN, 5);
+	let mut words = heap.alloc_array_root::<[u32; 3]>(TYPE_PLAIN, 5);
+	
+	for i in 0..bytes.len() {
+		bytes[i] = 200 + i as u8;
+	}
+	for i in 0..shorts.len() {
+		shorts[i] = 60000 + i as u16;
+	}
+	for i in 0..ints.len() {
+		ints[i] = 4000000000 + i as u32;
+	}
+	for i in 0..triples.len() {
+		triples[i] = [i as u8, i as u8 + 1, i as u8 + 2];
+	}
+	for i in 0..words.len() {
+		words[i] = [0xffffffff, i as u32, 0xffffffff];
+	}
+	
+	let mut links = heap.alloc_array_root::<MyLink>(TYPE_LINK, 4);
+	for i in 0..links.len() {
+		links[i] = MyLink {
+			target: alloc_struct(&heap, i as i32, 0, 0),
+			tag: 0xffffffff - i as u32
+		};
+	}
+	
+	churn(&heap);
+	
+	for i in 0..bytes.len() {
+		assert_eq!(bytes[i], 200 + i as u8);
+	}
+	for i in 0..shorts.len() {
+		assert_eq!(shorts[i], 60000 + i as u16);
+	}
+	for i in 0..ints.len() {
+		assert_eq!(ints[i], 4000000000 + i as u32);
+	}
+	for i in 0..triples.len() {
+		assert_eq!(triples[i], [i as u8, i as u8 + 1, i as u8 + 2]);
+	}
+	for i in 0..words.len() {
+		assert_eq!(words[i], [0xffffffff, i as u32, 0xffffffff]);
+	}
+	for i in 0..links.len() {
+		assert_eq!((links[i].target.a, links[i].tag), (i as i32, 0xffffffff - i as u32));
+	}
+	
+	// The accessors use the same stride as the allocator.
+	
+	assert_eq!(*shorts.as_ptr().item_ptr(6), 60006);
+	
+	let copy = heap.alloc_array_root::<u16>(TYPE_PLAIN, 7);
+	Array::copy(&shorts.as_ptr(), &mut copy.as_ptr(), 7);
+	assert_eq!(&*copy, &*shorts);
+	
+	let census = heap.census();
+	
+	assert_eq!(census.get(TYPE_PLAIN).bytes, (8 + 24) + (8 + 24) + (8 + 32) + (8 + 24) + (8 + 72) + (8 + 24));
+	assert_eq!(census.get(TYPE_LINK).bytes, 8 + 8 + 4 * 16);
+}
+
+fn hash_of<T: std::hash::Hash + ?Sized>(value: &T) -> u64 {
+	use std::hash::Hasher;
+	
+	let mut hasher = std::collections::hash_map::DefaultHasher::new();
+	value.hash(&mut hasher);
+	hasher.finish()
+}
+
+fn strings() {
+	let heap = create_heap();
+	
+	let hello = heap.alloc_string_root(TYPE_STRING, "hello");
+	let accents = heap.alloc_string_root(TYPE_STRING, "h\u{e9}llo w\u{f6}rld");
+	let snowman = heap.alloc_string_root(TYPE_STRING, "snow \u{2603} \u{1f600}");
+	let units = "hello".encode_utf16().collect::<Vec<_>>();
+	let hello_utf16 = heap.alloc_string_utf16_root(TYPE_STRING, &units);
+	let surrogate = heap.alloc_string_utf16_root(TYPE_STRING, &[0xd800, 0x41, 0x263a]);
+	
+	{
+		let _scope = heap.new_local_scope();
+		
+		let local = heap.alloc_string_local(TYPE_STRING, "local");
+		heap.gc();
+		assert_eq!(local.to_string(), "local");
+	}
+	
+	// The walker does not know the string type, so strings are never walked.
+	
+	churn(&heap);
+	
+	assert!(hello.is_latin1() && accents.is_latin1() && !snowman.is_latin1());
+	assert_eq!(hello.to_string(), "hello");
+	assert_eq!(accents.to_string(), "h\u{e9}llo w\u{f6}rld");
+	assert_eq!(format!("{}", *snowman), "snow \u{2603} \u{1f600}");
+	assert_eq!(snowman.len(), 9);
+	assert_eq!(snowman.unit(8), 0xde00);
+	assert_eq!(surrogate.to_string(), "\u{fffd}A\u{263a}");
+	
+	assert!(*hello == *"hello");
+	assert!(*hello == *hello_utf16);
+	assert!(*hello != *accents);
+	assert_eq!(hash_of(&*hello), hash_of(&*hello_utf16));
+	
+	let census = heap.census();
+	
+	assert_eq!(census.get(TYPE_STRING).objects, 5);
+	assert_eq!(census.get(TYPE_STRING).bytes, 24 + 32 + 40 + 24 + 24);
+}
+
+struct ArrayLengths {
+	lengths: Vec<(u32, usize)>
+}
+
+impl GcGraphVisitor for ArrayLengths {
+	fn object(&mut self, object: &GcObject) {
+		if object.is_array {
+			self.lengths.push((object.ty, object.len));
+		}
+	}
+}
+
+struct MyVecRef {
+	items: Ptr<GcVec<Ptr<MyStruct>>>
+}
+
+fn vectors() {
+	let heap = create_heap();
+	
+	let mut items = heap.alloc_vec_root::<Ptr<MyStruct>>(TYPE_REF, 0);
+	
+	for i in 0..1000 {
+		let item = alloc_struct(&heap, i, 0, 0);
+		items.push(&heap, item);
+		
+		if i % 100 == 0 {
+			alloc_garbage(&heap, 10000);
+		}
+	}
+	
+	let item = alloc_struct(&heap, -1, 0, 0);
+	items.insert(&heap, 0, item);
+	
+	assert_eq!(items.len(), 1001);
+	assert!(items.capacity() > 1001);
+	
+	heap.gc();
+	
+	assert_eq!(items[0].a, -1);
+	for i in 1..items.len() {
+		assert_eq!(items[i].a, i as i32 - 1);
+	}
+	
+	// Items past the length are not walked, so their objects are collected.
+	
+	assert_eq!(items.pop().unwrap().a, 999);
+	items.truncate(100);
+	heap.gc();
+	
+	assert_eq!(heap.census().get(TYPE_STRUCT).objects, 100);
+	assert_eq!(items[99].a, 98);
+	
+	// A vector stored in an object.
+	
+	let mut holder = heap.alloc_root::<MyVecRef>(TYPE_REF);
+	holder.items = items.as_ptr();
+	drop(items);
+	
+	holder.items.reserve(&heap, 500);
+	let capacity = holder.items.capacity();
+	
+	for i in 0..500 {
+		let item = alloc_struct(&heap, i, 1, 0);
+		holder.items.push(&heap, item);
+	}
+	
+	assert_eq!(holder.items.capacity(), capacity);
+	
+	alloc_garbage(&heap, 10000);
+	heap.gc();
+	
+	assert_eq!(holder.items.len(), 600);
+	assert_eq!((holder.items[599].a, holder.items[599].b), (499, 1));
+	
+	// The graph reports the length of the storage, not its capacity.
+	
+	let mut lengths = ArrayLengths { lengths: Vec::new() };
+	heap.walk_graph(&mut lengths);
+	
+	assert_eq!(lengths.lengths, vec![(TYPE_REF, 600)]);
+	
+	// Small items.
+	
+	let mut shorts = heap.alloc_vec_root::<u16>(TYPE_PLAIN, 4);
+	for i in 0..100 {
+		shorts.push(&heap, i);
+	}
+	heap.gc();
+	
+	assert_eq!(shorts.iter().map(|&i| i as usize).sum::<usize>(), 4950);
+}
+
+fn hash_maps() {
+	let heap = create_heap();
+	
+	let mut keys = heap.alloc_vec_root::<Ptr<MyStruct>>(TYPE_REF, 0);
+	let mut map = heap.alloc_hash_map_root::<Ptr<MyStruct>, Ptr<MyStruct>>(TYPE_ENTRY, 0);
+	
+	for i in 0..1000 {
+		let key = alloc_struct(&heap, i, 0, 0);
+		keys.push(&heap, key);
+		
+		let value = alloc_struct(&heap, i, 1, 0);
+		map.insert(&heap, keys[i as usize], value);
+		
+		if i % 100 == 0 {
+			alloc_garbage(&heap, 10000);
+		}
+	}
+	
+	// Identity hashes survive the keys being moved.
+	
+	let address = keys[0].ptr();
+	let hash = heap.identity_hash(address);
+	
+	alloc_garbage(&heap, 10000);
+	heap.gc();
+	
+	assert!(keys[0].ptr() != address);
+	assert_eq!(heap.identity_hash(keys[0].ptr()), hash);
+	
+	assert_eq!(map.len(), 1000);
+	for i in 0..1000 {
+		let value = *map.get(&heap, keys[i]).unwrap();
+		assert_eq!((value.a, value.b), (i as i32, 1));
+	}
+	
+	// Removed values are no longer walked.
+	
+	for i in (0..1000).filter(|i| i % 2 == 0) {
+		assert_eq!(map.remove(&heap, keys[i]).unwrap().a, i as i32);
+	}
+	
+	heap.gc();
+	
+	assert_eq!(heap.census().get(TYPE_STRUCT).objects, 1500);
+	assert_eq!(map.len(), 500);
+	assert!(!map.contains_key(&heap, keys[0]));
+	
+	// Looking up an object that was never a key does not assign it a hash.
+	
+	let stranger = alloc_struct(&heap, -1, 0, 0);
+	assert!(map.get(&heap, stranger).is_none());
+	assert_eq!(heap.identity_hash_if_assigned(stranger.ptr()), None);
+	assert_eq!(map.iter().filter(|&(key, value)| key.a == value.a).count(), 500);
+	
+	let replaced = map.insert(&heap, keys[1], keys[0]);
+	assert_eq!(replaced.unwrap().a, 1);
+	assert_eq!(map.get(&heap, keys[1]).unwrap().a, 0);
+	
+	// Reinserting reuses the deleted entries.
+	
+	for i in (0..1000).filter(|i| i % 2 == 0) {
+		map.insert(&heap, keys[i], keys[i]);
+	}
+	
+	heap.gc();
+	
+	assert_eq!(map.len(), 1000);
+	assert_eq!(map.get(&heap, keys[998]).unwrap().ptr(), keys[998].ptr());
+	
+	map.clear();
+	assert!(map.is_empty());
+	assert!(map.get(&heap, keys[1]).is_none());
+	
+	// Integer keys.
+	
+	let mut numbers = heap.alloc_hash_map_root::<i64, usize>(TYPE_STRUCT, 4);
+	for i in -500..500 {
+		numbers.insert(&heap, i, (i * i) as usize);
+	}
+	
+	heap.gc();
+	
+	assert_eq!(numbers.len(), 1000);
+	assert_eq!(*numbers.get(&heap, -20).unwrap(), 400);
+}
+
+struct Walker;
+
+impl Walker {
+	fn new() -> Walker {
+		Walker
+	}
+}
+
+impl GcWalker for Walker {
+	fn walk(&self, ty: u32, ptr: ptr_t, index: u32) -> GcWalk {
+		match ty {
+			TYPE_STRUCT => GcWalk::Skip,
+			TYPE_REF => GcWalk::Pointer,
+			TYPE_VALUE => GcWalk::Tagged(GcTag::new(VALUE_TAG_MASK, VALUE_TAG_POINTER)),
+			TYPE_FIELD | TYPE_LINK => {
+				match index {
+					0 => GcWalk::Pointer,
+					1 => GcWalk::Skip,
+					_ => GcWalk::End
+				}
+			}
+			TYPE_ENTRY => {
+				match index {
+					0 | 1 => GcWalk::Pointer,
+					_ => GcWalk::End
+				}
+			}
+			TYPE_CALLBACK => {
+				match index {
+					0 => GcWalk::Skip,
+					1 => {
+						// The boolean at the start indicates whether this is a reference.
+						
+						let is_ref = unsafe { *mem::transmute::<_, &bool>(ptr) };
+						if is_ref { GcWalk::Pointer } else { GcWalk::Skip }
+					}
+					_ => GcWalk::End
+				}
+			}
+			_ => panic!("{}", ty)
+		}
+	}
+}
+
+struct PrintListener;
+
+impl GcListener for PrintListener {
+	fn after_gc(&self, stats: &GcCollectionStats) {
+		println!("=== GC === allocated {} used {} ms {}", stats.heap_size_after, stats.bytes_after, stats.pause_ns / 1_000_000);
+	}
+}
+
+fn create_heap() -> GcHeap {
+	let heap = GcHeap::new(Box::new(Walker::new()), GcOpts::default());
+	heap.add_listener(Box::new(PrintListener));
+	heap
+}
+
+fn bench(msg: &str, callback: &Fn()) {
+	println!("");
+	println!("==> Running {}", msg);
+	println!("");
+	
+	let stopwatch = Stopwatch::new();
+	callback();
+	
+	println!("");
+	println!("==> {} took {}", msg, stopwatch.elapsed_ms());
+	println!("");
+}
+
+fn alloc_struct(heap: &GcHeap, a: i32, b: i32, c: i32) -> Ptr<MyStruct> {
+	unsafe {
+		let mut result = heap.alloc(TYPE_STRUCT);
+		
+		*result = MyStruct {
+			a: a,
+			b: b,
+			c: c
+		};
+		
+		result
+	}
+}
+
+fn large_allocs() {
+	let heap = create_heap();
+	
+	let mut small = Vec::new();
+	
+	for _ in 0..400000 {
+		let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+		
+		result.a = alloc_struct(&heap, 1, 2, 3);
+		result.b = alloc_struct(&heap, 4, 5, 6);
+		
+		small.push(Some(result));
+	}
+	
+//	println!("after init");
+//	print_stats(&heap);
+	
+	heap.gc();
+	
+//	println!("after init gc");
+//	print_stats(&heap);
+
+	for _ in 0..100 {
+		for i in 0..100 {
+			let mut offset = i;
+			let mut inc = 1;
+			
+			while offset < small.len() {
+				let mut result = heap.alloc_root::<MyStructWithRef>(TYPE_REF);
+			
+				result.a = alloc_struct(&heap, 1, 2, 3);
+				result.b = alloc_struct(&heap, 4, 5, 6);
+				
+				small[offset] = Some(result);
+				
+				offset += inc;
+				inc += 1;
+			}
+		}
+	}
+	
+//	println!("after replace");
+//	print_stats(&heap);
+
+	heap.gc();
+	
+//	println!("after replace gc");
+//	print_stats(&heap);
+	
+	for i in (0..4000).rev() {
+		small[i * 10] = None;
+	}
+	
+//	println!("after remove");
+//	print_stats(&heap);
+
+	heap.gc();
+	
+//	println!("after remove gc");
+	print_stats(&heap);
+}
+
+fn many_allocs() {
+	let heap = create_heap();
+	
+	for _ in 0..10 {
+		print_stats(&heap);
+		
+		let _scope = heap.new_local_scope();
+		
+		for _ in 0..400000 {
+			let mut result = heap.alloc_local::<MyStructWithRef>(TYPE_REF);
+			
+			result.a = alloc_struct(&heap, 1, 2, 3);
+			result.b = alloc_struct(&heap, 4, 5, 6);
+		}
+	}
+	
+	heap.gc();
+	
+	print_stats(&heap);
+}
+
+fn callback_type() {
+	let heap = create_heap();
+	
+	{
+		// Test without reference.
+		
+		let _scope = heap.new_local_scope();
+		
+		let mut result = heap.alloc_local(TYPE_CALLBACK);
+		
+		*result = MyMaybeRef {
+			is_ref: false,
+			value: 0
+		};
+		
+		heap.gc();
+		
+		print_stats(&heap);
+	}
+	
+	{
+		// Test with reference.
+		
+		let _scope = heap.new_local_scope();
+		
+		let mut result = heap.alloc_local(TYPE_CALLBACK);
+		
+		*result = MyMaybeRef {
+			is_ref: true,
+			value: alloc_struct(&heap, 1, 2, 3).ptr() as usize
+		};
+		
+		heap.gc();
+		
+		print_stats(&heap);
+		
+		let value : Ptr<MyStruct> = Ptr::from_ptr(result.value as ptr_t);
+		let my_struct = &*value;
+		
+		assert_eq!(1 + 2 + 3, my_struct.a + my_struct.b + my_struct.c);
+	}
+}